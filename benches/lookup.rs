@@ -0,0 +1,82 @@
+// Compares the perfect hash lookups of LocalityId against the match based
+// lookups the locality_id! macro used to generate.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use weather_union::LocalityId;
+
+macro_rules! locality_id {
+    (
+        $(
+        ($str:expr, $konst:ident, $area:expr, $lat_long:expr, $device:expr);
+        )+
+    ) => {
+        const IDS: &[&str] = &[$($str,)+];
+        fn match_area_name(id: &str) -> Option<&'static str> {
+            match id {
+                $(
+                $str => Some($area),
+                )+
+                _ => None
+            }
+        }
+        fn match_area_lat_long(id: &str) -> Option<(f64, f64)> {
+            match id {
+                $(
+                $str => Some($lat_long),
+                )+
+                _ => None
+            }
+        }
+    };
+}
+
+include!(concat!(env!("OUT_DIR"), "/localities.rs"));
+
+fn lookups(c: &mut Criterion) {
+    // Spread over the table, the last ids are the slowest for a match.
+    // The ids are parsed up front so each side does a single lookup per id,
+    // the from_str cases add parsing, which is what a stream of raw ids pays.
+    let ids: Vec<(&str, LocalityId)> = IDS.iter().step_by(37).map(|id| (*id, LocalityId::from_str(id).unwrap())).collect();
+    // Unknown ids, a miss on the built-in table also checks the ids installed at runtime
+    let misses: Vec<String> = (0..ids.len()).map(|i| format!("ZWL{:06}", i)).collect();
+    assert!(misses.iter().all(|id| LocalityId::from_str(id).is_err()));
+
+    let mut group = c.benchmark_group("locality_name");
+    group.bench_function("match", |b| b.iter(|| {
+        ids.iter().filter_map(|(id, _)| match_area_name(black_box(id))).count()
+    }));
+    group.bench_function("match miss", |b| b.iter(|| {
+        misses.iter().filter_map(|id| match_area_name(black_box(id))).count()
+    }));
+    group.bench_function("phf", |b| b.iter(|| {
+        ids.iter().filter_map(|(_, id)| black_box(id).locality_name()).count()
+    }));
+    group.bench_function("phf from_str", |b| b.iter(|| {
+        ids.iter().filter(|(id, _)| LocalityId::from_str(black_box(id)).is_ok_and(|id| id.locality_name().is_some())).count()
+    }));
+    group.bench_function("phf from_str miss", |b| b.iter(|| {
+        misses.iter().filter(|id| LocalityId::from_str(black_box(id)).is_ok_and(|id| id.locality_name().is_some())).count()
+    }));
+    group.finish();
+
+    let mut group = c.benchmark_group("locality_lat_long");
+    group.bench_function("match", |b| b.iter(|| {
+        ids.iter().filter_map(|(id, _)| match_area_lat_long(black_box(id))).count()
+    }));
+    group.bench_function("match miss", |b| b.iter(|| {
+        misses.iter().filter_map(|id| match_area_lat_long(black_box(id))).count()
+    }));
+    group.bench_function("phf", |b| b.iter(|| {
+        ids.iter().filter_map(|(_, id)| black_box(id).locality_lat_long()).count()
+    }));
+    group.bench_function("phf from_str", |b| b.iter(|| {
+        ids.iter().filter_map(|(id, _)| LocalityId::from_str(black_box(id)).ok()?.locality_lat_long()).count()
+    }));
+    group.bench_function("phf from_str miss", |b| b.iter(|| {
+        misses.iter().filter_map(|id| LocalityId::from_str(black_box(id)).ok()?.locality_lat_long()).count()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, lookups);
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+// Compares SpatialIndex queries against a brute force haversine scan of every locality.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use weather_union::geo::haversine_km;
+use weather_union::spatial::SpatialIndex;
+use weather_union::LocalityId;
+
+fn brute_force_nearest(point: (f64, f64), k: usize) -> Vec<(LocalityId, f64)> {
+    let mut all: Vec<(LocalityId, f64)> = LocalityId::ALL.iter()
+        .filter_map(|id| Some((*id, haversine_km(point, id.locality_lat_long()?))))
+        .collect();
+    all.sort_by(|a, b| a.1.total_cmp(&b.1));
+    all.truncate(k);
+    all
+}
+
+fn queries(c: &mut Criterion) {
+    let index = SpatialIndex::builtin();
+    // GPS pings around Bengaluru, Mumbai and Delhi
+    let pings = [(12.936787, 77.556079), (19.076090, 72.877426), (28.613939, 77.209023)];
+
+    let mut group = c.benchmark_group("nearest_5");
+    group.bench_function("brute_force", |b| b.iter(|| {
+        pings.iter().map(|p| brute_force_nearest(black_box(*p), 5).len()).sum::<usize>()
+    }));
+    group.bench_function("kd_tree", |b| b.iter(|| {
+        pings.iter().map(|p| index.nearest(black_box(*p), 5).len()).sum::<usize>()
+    }));
+    group.finish();
+
+    let mut group = c.benchmark_group("radius_5km");
+    group.bench_function("brute_force", |b| b.iter(|| {
+        pings.iter().map(|p| {
+            LocalityId::ALL.iter().filter(|id| haversine_km(black_box(*p), id.locality_lat_long().unwrap()) <= 5.0).count()
+        }).sum::<usize>()
+    }));
+    group.bench_function("kd_tree", |b| b.iter(|| {
+        pings.iter().map(|p| index.within_radius(black_box(*p), 5.0).len()).sum::<usize>()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, queries);
+criterion_main!(benches);
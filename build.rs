@@ -0,0 +1,44 @@
+// Generates the LocalityId constants, lookup tables and their perfect hash index from localities.txt,
+// failing the build if the table is malformed.
+
+use std::collections::HashSet;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+#[path = "src/table.rs"]
+mod table;
+
+fn main() {
+    println!("cargo:rerun-if-changed=localities.txt");
+    println!("cargo:rerun-if-changed=src/table.rs");
+
+    let source = fs::read_to_string("localities.txt").expect("Should have been able to read localities.txt");
+    let mut seen = HashSet::new();
+    let mut position = phf_codegen::Map::new();
+    let mut generated = String::from("locality_id! {\n");
+    for (index, line) in source.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue
+        }
+        let parsed = table::parse_line(line).unwrap_or_else(|reason| panic!("localities.txt:{}: {}", index + 1, reason));
+        if !seen.insert(parsed.id) {
+            panic!("localities.txt:{}: duplicate locality id {}", index + 1, parsed.id);
+        }
+        position.entry(parsed.id, &(seen.len() - 1).to_string());
+        writeln!(
+            generated,
+            "    ({:?}, {}, {:?}, ({:?}, {:?}), {});",
+            parsed.id, parsed.id, parsed.name, parsed.lat, parsed.long, parsed.device_type
+        ).unwrap();
+    }
+    generated.push_str("}\n");
+
+    // Perfect hash from id to position in the table, for O(1) lookups
+    let index = format!("static LOCALITY_INDEX: phf::Map<&'static str, usize> = {};\n", position.build());
+
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out.join("localities.rs"), generated).expect("Should have been able to write the generated localities");
+    fs::write(out.join("locality_index.rs"), index).expect("Should have been able to write the generated index");
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+
+use crate::{LocalityId, Metric, Reading};
+
+/// Summary statistics of one metric over a set of readings
+/// # Example usage
+/// ```
+/// use weather_union::aggregate::Summary;
+/// use weather_union::Metric;
+/// let summary = Summary::new(Metric::Temperature, [31.0, 29.5, 30.2]).unwrap();
+/// assert_eq!(summary.max(), 31.0);
+/// assert_eq!(summary.median(), 30.2);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    /// Wind directions are unwrapped around their mean, see [`Summary::new`]
+    sorted: Vec<f64>,
+    mean: f64,
+    circular: bool,
+}
+
+impl Summary {
+
+    /// Summarise the finite values of `values`, or None if there are none.\
+    /// [`Metric::WindDirection`] is averaged on the circle, so 350° and 10° average to 0°, and ordered
+    /// within 180° either side of that mean, so their median is 0°, their min 350° and their max 10°.
+    pub fn new(metric: Metric, values: impl IntoIterator<Item = f64>) -> Option<Summary> {
+        let mut sorted: Vec<f64> = values.into_iter().filter(|v| v.is_finite()).collect();
+        if sorted.is_empty() {
+            return None
+        }
+        let circular = metric == Metric::WindDirection;
+        let mean = if circular {
+            let mean = circular_mean(&sorted);
+            for value in sorted.iter_mut() {
+                *value = mean + (*value - mean + 180.0).rem_euclid(360.0) - 180.0;
+            }
+            mean
+        } else {
+            sorted.iter().sum::<f64>() / sorted.len() as f64
+        };
+        sorted.sort_by(f64::total_cmp);
+        Some(Summary { sorted, mean, circular })
+    }
+
+    /// Number of values summarised
+    pub fn count(&self) -> usize {
+        self.sorted.len()
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    pub fn median(&self) -> f64 {
+        self.percentile(50.0)
+    }
+
+    pub fn min(&self) -> f64 {
+        self.wrap(self.sorted[0])
+    }
+
+    pub fn max(&self) -> f64 {
+        self.wrap(self.sorted[self.sorted.len() - 1])
+    }
+
+    /// The `p`th percentile (0 to 100), linearly interpolated between the closest ranks.
+    pub fn percentile(&self, p: f64) -> f64 {
+        let rank = (p.clamp(0.0, 100.0) / 100.0) * (self.sorted.len() - 1) as f64;
+        let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+        self.wrap(self.sorted[low] + (self.sorted[high] - self.sorted[low]) * (rank - low as f64))
+    }
+
+    /// Back within [0, 360) for wind directions
+    fn wrap(&self, value: f64) -> f64 {
+        if self.circular { value.rem_euclid(360.0) } else { value }
+    }
+}
+
+/// Mean direction in degrees within [0, 360)
+fn circular_mean(degrees: &[f64]) -> f64 {
+    let (sin, cos) = degrees.iter().fold((0.0, 0.0), |(sin, cos), d| {
+        (sin + d.to_radians().sin(), cos + d.to_radians().cos())
+    });
+    sin.atan2(cos).to_degrees().rem_euclid(360.0)
+}
+
+/// Per metric summaries of a group of localities
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Aggregate {
+    /// Number of readings that went into the aggregate
+    pub localities: usize,
+    metrics: BTreeMap<Metric, Summary>,
+}
+
+impl Aggregate {
+
+    /// Aggregate readings, skipping metrics a device does not report and values missing from a reading.
+    pub fn new<'a>(readings: impl IntoIterator<Item = &'a Reading>) -> Aggregate {
+        let mut localities = 0;
+        let mut values: BTreeMap<Metric, Vec<f64>> = BTreeMap::new();
+        for reading in readings {
+            localities += 1;
+            for metric in Metric::ALL {
+                if let Some(value) = reading.metric(metric) {
+                    values.entry(metric).or_default().push(value);
+                }
+            }
+        }
+        let metrics = values.into_iter()
+            .filter_map(|(metric, values)| Some((metric, Summary::new(metric, values)?)))
+            .collect();
+        Aggregate { localities, metrics }
+    }
+
+    /// Summary of a metric, None if no locality in the group reported it.
+    pub fn get(&self, metric: Metric) -> Option<&Summary> {
+        self.metrics.get(&metric)
+    }
+
+    pub fn metrics(&self) -> impl Iterator<Item = (Metric, &Summary)> {
+        self.metrics.iter().map(|(metric, summary)| (*metric, summary))
+    }
+}
+
+/// Group readings by [`LocalityId::city`] and aggregate each city.\
+/// Readings of localities without a known city are dropped.
+/// # Example usage
+/// ```
+/// use weather_union::aggregate::by_city;
+/// use weather_union::{LocalityId, Metric, WeatherUnion};
+/// async fn example(){
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let mut readings = Vec::new();
+///     for id in LocalityId::in_city("Bengaluru") {
+///         if let Ok(reading) = client.locality_reading(id).await {
+///             readings.push((id, reading));
+///         }
+///     }
+///     let cities = by_city(&readings);
+///     let temperature = cities["Bengaluru"].get(Metric::Temperature).unwrap();
+///     println!("{} (min {}, max {})", temperature.mean(), temperature.min(), temperature.max());
+/// }
+/// ```
+pub fn by_city<'a>(readings: impl IntoIterator<Item = &'a (LocalityId, Reading)>) -> BTreeMap<&'static str, Aggregate> {
+    let mut grouped: BTreeMap<&'static str, Vec<&Reading>> = BTreeMap::new();
+    for (id, reading) in readings {
+        if let Some(city) = id.city() {
+            grouped.entry(city).or_default().push(reading);
+        }
+    }
+    grouped.into_iter().map(|(city, readings)| (city, Aggregate::new(readings))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aws, rgs, LocalityWeatherData};
+
+    #[test]
+    fn test_summary() {
+        let summary = Summary::new(Metric::Temperature, [4.0, 1.0, f64::NAN, 3.0, 2.0]).unwrap();
+        assert_eq!(summary.count(), 4);
+        assert_eq!(summary.mean(), 2.5);
+        assert_eq!(summary.median(), 2.5);
+        assert_eq!(summary.percentile(0.0), 1.0);
+        assert_eq!(summary.percentile(100.0), 4.0);
+        assert!((summary.percentile(90.0) - 3.7).abs() < 1e-9);
+        assert!(Summary::new(Metric::Humidity, []).is_none());
+    }
+
+    #[test]
+    fn test_circular_wind_direction() {
+        let summary = Summary::new(Metric::WindDirection, [350.0, 10.0]).unwrap();
+        assert!(summary.mean() < 1e-9 || (360.0 - summary.mean()) < 1e-9);
+        assert!(summary.median() < 1e-9 || (360.0 - summary.median()) < 1e-9);
+        assert!((summary.min() - 350.0).abs() < 1e-9 && (summary.max() - 10.0).abs() < 1e-9);
+        let summary = Summary::new(Metric::WindDirection, [355.0, 5.0, 15.0, 345.0, 20.0]).unwrap();
+        assert!((summary.median() - 5.0).abs() < 1e-9);
+        assert!((summary.percentile(25.0) - 355.0).abs() < 1e-9);
+        let summary = Summary::new(Metric::WindDirection, [80.0, 100.0]).unwrap();
+        assert!((summary.mean() - 90.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_by_city_ignores_unreported_metrics() {
+        let readings = [
+            (LocalityId::ZWL005764, LocalityWeatherData { wind_direction: 350.0, ..aws(30.0) }.into()),
+            (LocalityId::ZWL008752, LocalityWeatherData { wind_direction: 10.0, ..aws(32.0) }.into()),
+            (LocalityId::ZWL005996, rgs(0.0, 5.0).into()),
+            (LocalityId::ZWL002458, aws(24.0).into()), // Mysore
+        ];
+        let cities = by_city(&readings);
+        assert_eq!(cities.len(), 2);
+        let delhi = &cities["Delhi NCR"];
+        assert_eq!(delhi.localities, 3);
+        // The rain gauge's zero temperature is not counted
+        assert_eq!(delhi.get(Metric::Temperature).unwrap().count(), 2);
+        assert_eq!(delhi.get(Metric::Temperature).unwrap().mean(), 31.0);
+        assert_eq!(delhi.get(Metric::RainAccumulation).unwrap().max(), 5.0);
+        assert_eq!(cities["Mysore"].get(Metric::WindDirection).unwrap().mean(), 90.0);
+    }
+
+    #[test]
+    fn test_missing_values_are_skipped() {
+        let body = r#"{"status":"200","message":"","device_type":1,"locality_weather_data":
+            {"temperature":null,"humidity":70.0,"wind_speed":1.0,"wind_direction":180.0,"rain_intensity":0.0}}"#;
+        let gap = crate::process_payload(http::StatusCode::OK, body).unwrap();
+        let aggregate = Aggregate::new(&[aws(30.0).into(), gap, aws(32.0).into()]);
+        assert_eq!(aggregate.localities, 3);
+        let temperature = aggregate.get(Metric::Temperature).unwrap();
+        assert_eq!((temperature.count(), temperature.min(), temperature.mean()), (2, 30.0, 31.0));
+        assert_eq!(aggregate.get(Metric::RainAccumulation).unwrap().count(), 2);
+        assert_eq!(aggregate.get(Metric::Humidity).unwrap().max(), 70.0);
+    }
+}
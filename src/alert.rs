@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use crate::{LocalityId, Metric, Reading};
+
+/// Direction in which a rule's threshold is crossed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparator {
+    #[serde(alias = ">")]
+    Above,
+    #[serde(alias = "<")]
+    Below,
+}
+
+/// A threshold rule evaluated per locality
+/// # Example usage
+/// ```
+/// use weather_union::alert::AlertRules;
+/// let rules = AlertRules::from_toml(r#"
+///     [[rules]]
+///     name = "heavy rain"
+///     metric = "rain_intensity"
+///     comparator = "above"
+///     threshold = 20.0
+///     hysteresis = 5.0           # clears once back below 15.0
+///     min_duration_secs = 600    # must stay above 20.0 for ten minutes
+/// "#).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    pub metric: Metric,
+    pub comparator: Comparator,
+    pub threshold: f64,
+    /// How far back past the threshold a value must go before a raised alert clears.
+    #[serde(default)]
+    pub hysteresis: f64,
+    /// How long the threshold must be breached before the alert is raised.
+    #[serde(default, rename = "min_duration_secs", with = "secs")]
+    pub min_duration: Duration,
+}
+
+impl AlertRule {
+    fn breached(&self, value: f64) -> bool {
+        match self.comparator {
+            Comparator::Above => value > self.threshold,
+            Comparator::Below => value < self.threshold,
+        }
+    }
+
+    fn recovered(&self, value: f64) -> bool {
+        match self.comparator {
+            Comparator::Above => value <= self.threshold - self.hysteresis,
+            Comparator::Below => value >= self.threshold + self.hysteresis,
+        }
+    }
+}
+
+/// A set of rules as loaded from a TOML or JSON document with a top level `rules` list
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AlertRules {
+    pub rules: Vec<AlertRule>,
+}
+
+#[derive(Debug)]
+pub enum RuleParseError {
+    Json(serde_json::Error), Toml(toml::de::Error), Invalid(String)
+}
+
+impl AlertRules {
+
+    pub fn from_json(source: &str) -> Result<AlertRules, RuleParseError> {
+        serde_json::from_str::<AlertRules>(source).map_err(RuleParseError::Json)?.validated()
+    }
+
+    pub fn from_toml(source: &str) -> Result<AlertRules, RuleParseError> {
+        toml::from_str::<AlertRules>(source).map_err(RuleParseError::Toml)?.validated()
+    }
+
+    fn validated(self) -> Result<AlertRules, RuleParseError> {
+        for rule in &self.rules {
+            if !rule.threshold.is_finite() {
+                return Err(RuleParseError::Invalid(format!("rule '{}' has a non finite threshold", rule.name)))
+            }
+            if !(rule.hysteresis.is_finite() && rule.hysteresis >= 0.0) {
+                return Err(RuleParseError::Invalid(format!("rule '{}' has a negative or non finite hysteresis", rule.name)))
+            }
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlertKind {
+    Raised, Cleared
+}
+
+/// Emitted by [`AlertEngine::observe`] when a rule changes state for a locality
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertEvent {
+    pub kind: AlertKind,
+    pub rule: String,
+    pub locality: LocalityId,
+    pub metric: Metric,
+    pub value: f64,
+    pub at: SystemTime,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum RuleState {
+    Clear { breached_since: Option<SystemTime> },
+    Raised,
+}
+
+/// Evaluates [`AlertRules`] over successive readings of each locality
+/// # Example usage
+/// ```
+/// use std::time::SystemTime;
+/// use weather_union::alert::{AlertEngine, AlertRules};
+/// use weather_union::{LocalityId, WeatherUnion};
+/// async fn example(rules: AlertRules){
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let mut engine = AlertEngine::new(rules);
+///     let data = client.locality_reading(LocalityId::ZWL005764).await.unwrap();
+///     for event in engine.observe(LocalityId::ZWL005764, SystemTime::now(), &data) {
+///         println!("{:?} {} at {}", event.kind, event.rule, event.locality);
+///     }
+/// }
+/// ```
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    states: HashMap<(LocalityId, usize), RuleState>,
+}
+
+impl AlertEngine {
+
+    pub fn new(rules: AlertRules) -> AlertEngine {
+        AlertEngine { rules: rules.rules, states: HashMap::new() }
+    }
+
+    /// Feed a reading taken at `at`, returning the alerts raised or cleared by it.\
+    /// Metrics the device does not report leave the rule state untouched.
+    pub fn observe(&mut self, locality: LocalityId, at: SystemTime, reading: &Reading) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            let Some(value) = reading.metric(rule.metric) else { continue };
+            let state = self.states.entry((locality, index)).or_insert(RuleState::Clear { breached_since: None });
+            let kind = match *state {
+                RuleState::Clear { breached_since } if rule.breached(value) => {
+                    let since = breached_since.unwrap_or(at);
+                    if at.duration_since(since).unwrap_or(Duration::ZERO) >= rule.min_duration {
+                        *state = RuleState::Raised;
+                        AlertKind::Raised
+                    } else {
+                        *state = RuleState::Clear { breached_since: Some(since) };
+                        continue
+                    }
+                }
+                RuleState::Clear { .. } => {
+                    *state = RuleState::Clear { breached_since: None };
+                    continue
+                }
+                RuleState::Raised if rule.recovered(value) => {
+                    *state = RuleState::Clear { breached_since: None };
+                    AlertKind::Cleared
+                }
+                RuleState::Raised => continue,
+            };
+            events.push(AlertEvent { kind, rule: rule.name.clone(), locality, metric: rule.metric, value, at });
+        }
+        events
+    }
+
+    /// Rules currently raised, per locality.
+    pub fn raised(&self) -> impl Iterator<Item = (LocalityId, &AlertRule)> {
+        self.states.iter()
+            .filter(|(_, state)| matches!(state, RuleState::Raised))
+            .map(|((locality, index), _)| (*locality, &self.rules[*index]))
+    }
+}
+
+mod secs {
+    use std::time::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aws, rgs, LocalityWeatherData};
+
+    fn minutes(m: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(m * 60)
+    }
+
+    #[test]
+    fn test_raise_after_min_duration_and_clear_with_hysteresis() {
+        let rules = AlertRules::from_json(r#"{"rules": [{"name": "rain", "metric": "rain_intensity",
+            "comparator": ">", "threshold": 20.0, "hysteresis": 5.0, "min_duration_secs": 600}]}"#).unwrap();
+        let mut engine = AlertEngine::new(rules);
+        let id = LocalityId::ZWL005764;
+        assert!(engine.observe(id, minutes(0), &rgs(25.0, 0.0).into()).is_empty());
+        assert!(engine.observe(id, minutes(5), &rgs(30.0, 0.0).into()).is_empty());
+        let raised = engine.observe(id, minutes(10), &rgs(22.0, 0.0).into());
+        assert_eq!(raised.len(), 1);
+        assert_eq!(raised[0].kind, AlertKind::Raised);
+        assert_eq!(engine.raised().count(), 1);
+        // Inside the hysteresis band, stays raised
+        assert!(engine.observe(id, minutes(15), &rgs(18.0, 0.0).into()).is_empty());
+        let cleared = engine.observe(id, minutes(20), &rgs(14.0, 0.0).into());
+        assert_eq!(cleared[0].kind, AlertKind::Cleared);
+        assert_eq!(engine.raised().count(), 0);
+    }
+
+    #[test]
+    fn test_interrupted_breach_restarts_duration() {
+        let rules = AlertRules::from_toml(r#"
+            [[rules]]
+            name = "cold"
+            metric = "temperature"
+            comparator = "below"
+            threshold = 5.0
+            min_duration_secs = 600
+        "#).unwrap();
+        let mut engine = AlertEngine::new(rules);
+        let id = LocalityId::ZWL005764;
+        assert!(engine.observe(id, minutes(0), &aws(4.0).into()).is_empty());
+        assert!(engine.observe(id, minutes(5), &aws(6.0).into()).is_empty());
+        assert!(engine.observe(id, minutes(10), &aws(4.0).into()).is_empty());
+        assert_eq!(engine.observe(id, minutes(20), &aws(4.0).into()).len(), 1);
+        // Rain gauges do not report temperature and leave the rule alone
+        assert!(engine.observe(id, minutes(30), &LocalityWeatherData { temperature: 20.0, ..rgs(0.0, 0.0) }.into()).is_empty());
+        assert_eq!(engine.raised().count(), 1);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(matches!(AlertRules::from_json(r#"{"rules": [{"name": "x", "metric": "humidity",
+            "comparator": "above", "threshold": 90.0, "hysteresis": -1.0}]}"#), Err(RuleParseError::Invalid(_))));
+        assert!(matches!(AlertRules::from_toml("rules = 3"), Err(RuleParseError::Toml(_))));
+    }
+}
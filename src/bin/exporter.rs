@@ -0,0 +1,131 @@
+//! Prometheus exporter polling a set of localities, built with `--features exporter`.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use axum::extract::State;
+use axum::http::header;
+use axum::routing::get;
+use axum::Router;
+use futures_util::StreamExt;
+use weather_union::cli::{city_ids, ClientArgs};
+use weather_union::prometheus::{Exporter, CONTENT_TYPE};
+use weather_union::LocalityId;
+
+const USAGE: &str = "\
+Usage: weather-union-exporter [OPTIONS] [LOCALITY_ID]...
+
+Polls the given localities and serves their latest readings on /metrics.
+
+Options:
+  --listen ADDR      Address to serve on (default 127.0.0.1:9898)
+  --interval SECS    Poll every locality once per interval (default 300)
+  --city NAME        Also poll every locality of a city, may be repeated
+  --key-file PATH    Read an api key from a file, may be repeated to rotate keys
+  --base-url URL     Send requests to another host, e.g. a proxy
+The api key is read from WEATHER_UNION_API_KEY when no --key-file is given.";
+
+#[derive(Debug)]
+struct Args {
+    listen: SocketAddr,
+    interval: Duration,
+    client: ClientArgs,
+    ids: Vec<LocalityId>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        listen: SocketAddr::from(([127, 0, 0, 1], 9898)),
+        interval: Duration::from_secs(300),
+        client: ClientArgs::default(),
+        ids: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        if parsed.client.parse(&arg, &mut value)? {
+            continue
+        }
+        match arg.as_str() {
+            "--listen" => parsed.listen = value("--listen")?.parse().map_err(|e| format!("--listen: {}", e))?,
+            "--interval" => {
+                let secs: u64 = value("--interval")?.parse().map_err(|e| format!("--interval: {}", e))?;
+                parsed.interval = Duration::from_secs(secs.max(1));
+            }
+            "--city" => parsed.ids.extend(city_ids(&value("--city")?)?),
+            "-h" | "--help" => return Err(String::new()),
+            id => parsed.ids.push(LocalityId::from_str(id).map_err(|_| format!("unknown locality id {}", id))?),
+        }
+    }
+    parsed.ids.sort();
+    parsed.ids.dedup();
+    if parsed.ids.is_empty() {
+        return Err("no localities to poll".to_string())
+    }
+    Ok(parsed)
+}
+
+async fn metrics(State(exporter): State<Arc<Mutex<Exporter>>>) -> ([(header::HeaderName, &'static str); 1], String) {
+    let text = exporter.lock().unwrap_or_else(|e| e.into_inner()).render();
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], text)
+}
+
+#[tokio::main]
+async fn main() {
+    let args = parse_args(std::env::args().skip(1)).unwrap_or_else(|error| {
+        if !error.is_empty() {
+            eprintln!("error: {}\n", error);
+        }
+        eprintln!("{}", USAGE);
+        std::process::exit(2)
+    });
+    let client = args.client.client().unwrap_or_else(|error| {
+        eprintln!("error: {}", error);
+        std::process::exit(2)
+    });
+
+    let exporter = Arc::new(Mutex::new(Exporter::new()));
+    let poller = exporter.clone();
+    let (ids, interval) = (args.ids.clone(), args.interval);
+    tokio::spawn(async move {
+        let mut readings = Box::pin(client.watch(ids, interval));
+        while let Some((id, result)) = readings.next().await {
+            let mut exporter = poller.lock().unwrap_or_else(|e| e.into_inner());
+            exporter.observe(id, &result, SystemTime::now());
+            exporter.set_key_stats(&client.key_stats());
+        }
+    });
+
+    let router = Router::new().route("/metrics", get(metrics)).with_state(exporter);
+    let listener = tokio::net::TcpListener::bind(args.listen).await.unwrap_or_else(|error| {
+        eprintln!("error: could not listen on {}: {}", args.listen, error);
+        std::process::exit(1)
+    });
+    println!("serving {} localities on http://{}/metrics", args.ids.len(), args.listen);
+    if let Err(error) = axum::serve(listener, router).await {
+        eprintln!("error: {}", error);
+        std::process::exit(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse(&["--interval", "60", "--city", "Surat", "ZWL005764", "--listen", "0.0.0.0:9000"]).unwrap();
+        assert_eq!(args.interval, Duration::from_secs(60));
+        assert_eq!(args.listen.port(), 9000);
+        assert_eq!(args.ids.len(), LocalityId::in_city("Surat").count() + 1);
+
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["ZWL000000"]).unwrap_err().contains("ZWL000000"));
+        assert!(parse(&["--city", "Atlantis"]).is_err());
+        assert!(parse(&["ZWL005764", "--interval"]).is_err());
+    }
+}
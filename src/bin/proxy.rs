@@ -0,0 +1,108 @@
+//! Caching proxy sharing one upstream api key between consumers, built with `--features proxy`.
+
+use std::net::SocketAddr;
+
+use weather_union::cli::ClientArgs;
+use weather_union::proxy::{Proxy, ProxyConfig};
+
+const USAGE: &str = "\
+Usage: weather-union-proxy --config PATH [OPTIONS]
+
+Serves the WeatherUnion endpoints to the consumers listed in the config file,
+caching responses and charging each consumer's quota for requests sent upstream.
+
+Options:
+  --config PATH      TOML file with cache_ttl_secs and [[consumer]] entries
+  --listen ADDR      Address to serve on (default 127.0.0.1:8080)
+  --key-file PATH    Read an upstream api key from a file, may be repeated to rotate keys
+  --base-url URL     Send upstream requests to another host
+The upstream api key is read from WEATHER_UNION_API_KEY when no --key-file is given.";
+
+#[derive(Debug)]
+struct Args {
+    config: String,
+    listen: SocketAddr,
+    client: ClientArgs,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut config = None;
+    let mut parsed = Args {
+        config: String::new(),
+        listen: SocketAddr::from(([127, 0, 0, 1], 8080)),
+        client: ClientArgs::default(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        if parsed.client.parse(&arg, &mut value)? {
+            continue
+        }
+        match arg.as_str() {
+            "--config" => config = Some(value("--config")?),
+            "--listen" => parsed.listen = value("--listen")?.parse().map_err(|e| format!("--listen: {}", e))?,
+            "-h" | "--help" => return Err(String::new()),
+            other => return Err(format!("unexpected argument {}", other)),
+        }
+    }
+    parsed.config = config.ok_or("--config is required")?;
+    Ok(parsed)
+}
+
+fn config(args: &Args) -> Result<ProxyConfig, String> {
+    let text = std::fs::read_to_string(&args.config).map_err(|e| format!("{}: {}", args.config, e))?;
+    let config = ProxyConfig::from_toml(&text).map_err(|e| format!("{}: {:?}", args.config, e))?;
+    if config.consumers.is_empty() {
+        return Err(format!("{}: no consumers configured", args.config))
+    }
+    Ok(config)
+}
+
+#[tokio::main]
+async fn main() {
+    let args = parse_args(std::env::args().skip(1)).unwrap_or_else(|error| {
+        if !error.is_empty() {
+            eprintln!("error: {}\n", error);
+        }
+        eprintln!("{}", USAGE);
+        std::process::exit(2)
+    });
+    let (config, client) = match config(&args).and_then(|config| Ok((config, args.client.client()?))) {
+        Ok(setup) => setup,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2)
+        }
+    };
+
+    let consumers = config.consumers.len();
+    let listener = tokio::net::TcpListener::bind(args.listen).await.unwrap_or_else(|error| {
+        eprintln!("error: could not listen on {}: {}", args.listen, error);
+        std::process::exit(1)
+    });
+    println!("proxying for {} consumers on http://{}", consumers, args.listen);
+    if let Err(error) = Proxy::new(client, config).serve(listener).await {
+        eprintln!("error: {}", error);
+        std::process::exit(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse(&["--config", "proxy.toml", "--listen", "0.0.0.0:9000", "--key-file", "a", "--key-file", "b"]).unwrap();
+        assert_eq!(args.config, "proxy.toml");
+        assert_eq!(args.listen.port(), 9000);
+        assert_eq!(args.client.key_files, ["a", "b"]);
+
+        assert!(parse(&[]).unwrap_err().contains("--config"));
+        assert!(parse(&["--config", "proxy.toml", "ZWL005764"]).is_err());
+        assert!(parse(&["--config"]).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! JSON API over locality metadata, city summaries and stored readings, built with `--features server`.
+
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use futures_util::StreamExt;
+use weather_union::cli::{city_ids, ClientArgs};
+use weather_union::history::Retention;
+use weather_union::server::ApiServer;
+use weather_union::{LocalityId, WeatherUnion};
+
+const USAGE: &str = "\
+Usage: weather-union-server [OPTIONS] [LOCALITY_ID]...
+
+Serves /localities, /nearest, /city/{name}/summary, /locality/{id}/history and /openapi.json.
+The given localities are polled in the background to fill their history.
+
+Options:
+  --listen ADDR      Address to serve on (default 127.0.0.1:8000)
+  --max-age SECS     Reuse readings younger than this in city summaries (default 300)
+  --interval SECS    Poll every given locality once per interval (default 300)
+  --retention DAYS   Drop readings older than this, checked hourly (default 30)
+  --db PATH          Keep history in a SQLite database, needs the sqlite feature
+  --city NAME        Also poll every locality of a city, may be repeated
+  --key-file PATH    Read an api key from a file, may be repeated to rotate keys
+  --base-url URL     Send requests to another host, e.g. a proxy
+The api key is read from WEATHER_UNION_API_KEY when no --key-file is given.";
+
+#[derive(Debug)]
+struct Args {
+    listen: SocketAddr,
+    max_age: Duration,
+    interval: Duration,
+    retention: Duration,
+    db: Option<String>,
+    client: ClientArgs,
+    ids: Vec<LocalityId>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        listen: SocketAddr::from(([127, 0, 0, 1], 8000)),
+        max_age: Duration::from_secs(300),
+        interval: Duration::from_secs(300),
+        retention: Duration::from_secs(30 * 86400),
+        db: None,
+        client: ClientArgs::default(),
+        ids: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        if parsed.client.parse(&arg, &mut value)? {
+            continue
+        }
+        match arg.as_str() {
+            "--listen" => parsed.listen = value("--listen")?.parse().map_err(|e| format!("--listen: {}", e))?,
+            "--max-age" => {
+                let secs: u64 = value("--max-age")?.parse().map_err(|e| format!("--max-age: {}", e))?;
+                parsed.max_age = Duration::from_secs(secs);
+            }
+            "--interval" => {
+                let secs: u64 = value("--interval")?.parse().map_err(|e| format!("--interval: {}", e))?;
+                parsed.interval = Duration::from_secs(secs.max(1));
+            }
+            "--retention" => {
+                let days: u64 = value("--retention")?.parse().map_err(|e| format!("--retention: {}", e))?;
+                parsed.retention = days.checked_mul(86400).map(Duration::from_secs).ok_or("--retention: too many days")?;
+            }
+            "--db" => parsed.db = Some(value("--db")?),
+            "--city" => parsed.ids.extend(city_ids(&value("--city")?)?),
+            "-h" | "--help" => return Err(String::new()),
+            id => parsed.ids.push(LocalityId::from_str(id).map_err(|_| format!("unknown locality id {}", id))?),
+        }
+    }
+    parsed.ids.sort();
+    parsed.ids.dedup();
+    Ok(parsed)
+}
+
+fn server(args: &Args, client: WeatherUnion) -> Result<ApiServer, String> {
+    let Some(path) = &args.db else {
+        return Ok(ApiServer::new(client, args.max_age))
+    };
+    #[cfg(feature = "sqlite")]
+    {
+        let history = weather_union::history::SqliteHistory::open(path).map_err(|e| format!("{}: {:?}", path, e))?;
+        Ok(ApiServer::with_history(client, args.max_age, history))
+    }
+    #[cfg(not(feature = "sqlite"))]
+    {
+        let _ = client;
+        Err(format!("--db {} needs a build with --features sqlite", path))
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = parse_args(std::env::args().skip(1)).unwrap_or_else(|error| {
+        if !error.is_empty() {
+            eprintln!("error: {}\n", error);
+        }
+        eprintln!("{}", USAGE);
+        std::process::exit(2)
+    });
+    let server = args.client.client().and_then(|client| server(&args, client)).unwrap_or_else(|error| {
+        eprintln!("error: {}", error);
+        std::process::exit(2)
+    });
+
+    let pruner = server.clone();
+    let retention = Retention::new().max_age(args.retention);
+    tokio::spawn(async move {
+        let mut hourly = tokio::time::interval(Duration::from_secs(3600));
+        loop {
+            hourly.tick().await;
+            if let Err(error) = pruner.prune(retention).await {
+                eprintln!("error: pruning history: {:?}", error);
+            }
+        }
+    });
+    if !args.ids.is_empty() {
+        let poller = server.clone();
+        let (ids, interval) = (args.ids.clone(), args.interval);
+        tokio::spawn(async move {
+            let mut readings = Box::pin(poller.client().watch(ids, interval));
+            while let Some((id, result)) = readings.next().await {
+                if let Ok(data) = result {
+                    if let Err(error) = poller.record(id, data, SystemTime::now()).await {
+                        eprintln!("error: recording {}: {:?}", id, error);
+                    }
+                }
+            }
+        });
+    }
+
+    let listener = tokio::net::TcpListener::bind(args.listen).await.unwrap_or_else(|error| {
+        eprintln!("error: could not listen on {}: {}", args.listen, error);
+        std::process::exit(1)
+    });
+    println!("serving on http://{}, polling {} localities", args.listen, args.ids.len());
+    if let Err(error) = server.serve(listener).await {
+        eprintln!("error: {}", error);
+        std::process::exit(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse(&["--max-age", "60", "--retention", "7", "--db", "readings.db", "--city", "Surat", "ZWL005764", "--listen", "0.0.0.0:9000"]).unwrap();
+        assert_eq!(args.max_age, Duration::from_secs(60));
+        assert_eq!(args.listen.port(), 9000);
+        assert_eq!(args.retention, Duration::from_secs(7 * 86400));
+        assert_eq!(args.db.as_deref(), Some("readings.db"));
+        assert_eq!(args.ids.len(), LocalityId::in_city("Surat").count() + 1);
+
+        assert!(parse(&[]).unwrap().ids.is_empty());
+        assert!(parse(&["ZWL000000"]).unwrap_err().contains("ZWL000000"));
+        assert!(parse(&["--max-age", "soon"]).is_err());
+        assert!(parse(&["--retention", "18446744073709551615"]).unwrap_err().contains("--retention"));
+    }
+}
@@ -0,0 +1,226 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::transport::{HttpRequest, HttpResponse, Transport, TransportError, TransportFuture};
+
+/// Environment variable that switches [`Cassette::from_env`] to record mode when set to `record`
+pub const MODE_VAR: &str = "WEATHER_UNION_CASSETTE";
+
+/// One recorded request/response pair, stored as a line of a JSONL cassette.\
+/// The api key is never recorded, `path` is relative to the base url.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum CassetteError {
+    Io(io::Error),
+    Json { line: usize, error: serde_json::Error },
+    /// Replaying a request the cassette has no response for
+    Unrecorded { method: String, path: String },
+}
+
+#[derive(Debug)]
+enum Mode {
+    Record,
+    Replay,
+}
+
+/// Records the HTTP interactions of a [`crate::WeatherUnion`] to a JSONL file, or serves them back.\
+/// In replay mode a request is answered by the first unused interaction with the same method, path
+/// and query parameters (in any order), once all matching interactions are used the last one is repeated.
+/// # Example usage
+/// ```
+/// use weather_union::WeatherUnion;
+/// use weather_union::cassette::Cassette;
+/// async fn example(){
+///     // Replays tests/cassettes/banashankari.jsonl, or records it when WEATHER_UNION_CASSETTE=record
+///     let cassette = Cassette::from_env("tests/cassettes/banashankari.jsonl").unwrap();
+///     let client = WeatherUnion::from_key("api_key".to_string()).with_cassette(cassette);
+///     let data = client.locality_id("ZWL003467").await;
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: Mode,
+    interactions: Mutex<Vec<(Interaction, bool)>>,
+}
+
+impl Cassette {
+
+    /// Write every interaction to the file at `path`, creating it if needed.\
+    /// An existing file is emptied first, so re-recording replaces what it held.
+    pub fn record(path: impl AsRef<Path>) -> Result<Cassette, CassetteError> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(CassetteError::Io)?;
+        }
+        OpenOptions::new().create(true).write(true).truncate(true).open(&path).map_err(CassetteError::Io)?;
+        Ok(Cassette { path, mode: Mode::Record, interactions: Mutex::new(Vec::new()) })
+    }
+
+    /// Serve responses from the file at `path`, blank lines are ignored.
+    pub fn replay(path: impl AsRef<Path>) -> Result<Cassette, CassetteError> {
+        let path = path.as_ref().to_path_buf();
+        let text = fs::read_to_string(&path).map_err(CassetteError::Io)?;
+        let mut interactions = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue
+            }
+            let interaction = serde_json::from_str(line).map_err(|error| CassetteError::Json { line: i + 1, error })?;
+            interactions.push((interaction, false));
+        }
+        Ok(Cassette { path, mode: Mode::Replay, interactions: Mutex::new(interactions) })
+    }
+
+    /// Record if the `WEATHER_UNION_CASSETTE` environment variable is `record`, replay otherwise.
+    pub fn from_env(path: impl AsRef<Path>) -> Result<Cassette, CassetteError> {
+        match std::env::var(MODE_VAR) {
+            Ok(mode) if mode == "record" => Cassette::record(path),
+            _ => Cassette::replay(path),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.mode, Mode::Record)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Interactions recorded or loaded so far
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.lock().iter().map(|(i, _)| i.clone()).collect()
+    }
+
+    /// The recorded (status, body) for a request, None if nothing matches.
+    pub fn find(&self, method: &str, path: &str) -> Option<(u16, String)> {
+        let mut interactions = self.lock();
+        let wanted = normalize(path);
+        let matching: Vec<usize> = interactions.iter().enumerate()
+            .filter(|(_, (i, _))| i.method.eq_ignore_ascii_case(method) && normalize(&i.path) == wanted)
+            .map(|(index, _)| index)
+            .collect();
+        let index = matching.iter().copied().find(|index| !interactions[*index].1).or(matching.last().copied())?;
+        let (interaction, used) = &mut interactions[index];
+        *used = true;
+        Some((interaction.status, interaction.body.clone()))
+    }
+
+    /// Append an interaction, writing it to the file straight away in record mode.
+    pub fn push(&self, interaction: Interaction) -> Result<(), CassetteError> {
+        let mut interactions = self.lock();
+        if self.is_recording() {
+            let mut line = serde_json::to_string(&interaction)
+                .map_err(|error| CassetteError::Json { line: interactions.len() + 1, error })?;
+            line.push('\n');
+            OpenOptions::new().append(true).open(&self.path)
+                .and_then(|mut file| file.write_all(line.as_bytes()))
+                .map_err(CassetteError::Io)?;
+        }
+        interactions.push((interaction, true));
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(Interaction, bool)>> {
+        self.interactions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A [`Transport`] that records what `inner` returns to a [`Cassette`], or replays it without calling `inner`.\
+/// [`crate::WeatherUnion::with_cassette`] wraps the client's current transport in one of these.
+pub struct CassetteTransport<T> {
+    cassette: Cassette,
+    inner: T,
+}
+
+impl<T: Transport> CassetteTransport<T> {
+
+    pub fn new(cassette: Cassette, inner: T) -> CassetteTransport<T> {
+        CassetteTransport { cassette, inner }
+    }
+
+    pub fn cassette(&self) -> &Cassette {
+        &self.cassette
+    }
+}
+
+impl<T: Transport> Transport for CassetteTransport<T> {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let method = request.method.clone();
+            let path = request.path().to_string();
+            if !self.cassette.is_recording() {
+                return match self.cassette.find(&method, &path) {
+                    Some((status, body)) => Ok(HttpResponse::new(status, body)),
+                    None => Err(TransportError::Cassette(CassetteError::Unrecorded { method, path })),
+                }
+            }
+            let response = self.inner.send(request).await?;
+            let interaction = Interaction { method, path, status: response.status, body: response.body.clone() };
+            self.cassette.push(interaction).map_err(TransportError::Cassette)?;
+            Ok(response)
+        })
+    }
+}
+
+/// Path with its query parameters sorted, so parameter order does not affect matching
+fn normalize(path: &str) -> (String, Vec<String>) {
+    match path.split_once('?') {
+        Some((path, query)) => {
+            let mut params: Vec<String> = query.split('&').filter(|p| !p.is_empty()).map(str::to_string).collect();
+            params.sort();
+            (path.to_string(), params)
+        }
+        None => (path.to_string(), Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interaction(path: &str, body: &str) -> Interaction {
+        Interaction { method: "GET".to_string(), path: path.to_string(), status: 200, body: body.to_string() }
+    }
+
+    #[test]
+    fn test_record_then_replay() {
+        let path = std::env::temp_dir().join(format!("weather_union_cassette_{}.jsonl", std::process::id()));
+        // Left over from an earlier recording, replaced by the new one
+        fs::write(&path, serde_json::to_string(&interaction("/a?x=1&y=2", "stale")).unwrap() + "\n").unwrap();
+        let recorder = Cassette::record(&path).unwrap();
+        recorder.push(interaction("/a?x=1&y=2", "first")).unwrap();
+        recorder.push(interaction("/a?x=1&y=2", "second")).unwrap();
+        recorder.push(interaction("/b", "other")).unwrap();
+
+        let player = Cassette::replay(&path).unwrap();
+        assert_eq!(player.interactions().len(), 3);
+        assert_eq!(player.find("GET", "/a?y=2&x=1"), Some((200, "first".to_string())));
+        assert_eq!(player.find("GET", "/a?x=1&y=2"), Some((200, "second".to_string())));
+        // Exhausted, the last match repeats
+        assert_eq!(player.find("get", "/a?x=1&y=2"), Some((200, "second".to_string())));
+        assert_eq!(player.find("GET", "/a?x=2&y=2"), None);
+        assert_eq!(player.find("POST", "/b"), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_reports_bad_line() {
+        let path = std::env::temp_dir().join(format!("weather_union_cassette_bad_{}.jsonl", std::process::id()));
+        fs::write(&path, "\n{\"method\":\"GET\"}\n").unwrap();
+        assert!(matches!(Cassette::replay(&path), Err(CassetteError::Json { line: 2, .. })));
+        fs::remove_file(&path).unwrap();
+    }
+}
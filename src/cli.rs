@@ -0,0 +1,85 @@
+//! Options shared by the `weather-union-*` binaries, built with the `exporter`, `proxy` or `server` feature.
+
+use crate::keys::KeyPool;
+use crate::{LocalityId, WeatherUnion};
+
+/// Environment variable holding the api key when no `--key-file` is given
+pub const API_KEY_VAR: &str = "WEATHER_UNION_API_KEY";
+
+/// `--key-file` and `--base-url`, building the client every binary uses
+/// # Example
+/// ```
+/// use weather_union::cli::ClientArgs;
+/// let mut client_args = ClientArgs::default();
+/// let mut args = ["--key-file", "key.txt", "--base-url", "http://localhost:8080/"].map(String::from).into_iter();
+/// while let Some(arg) = args.next() {
+///     let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+///     assert!(client_args.parse(&arg, &mut value).unwrap());
+/// }
+/// assert_eq!(client_args.base_url.as_deref(), Some("http://localhost:8080/"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientArgs {
+    pub key_files: Vec<String>,
+    pub base_url: Option<String>,
+}
+
+impl ClientArgs {
+
+    /// Take `arg` if it is a client option, reading its value with `value`.\
+    /// Returns false for any other argument.
+    pub fn parse(&mut self, arg: &str, value: &mut impl FnMut(&str) -> Result<String, String>) -> Result<bool, String> {
+        match arg {
+            "--key-file" => self.key_files.push(value(arg)?),
+            "--base-url" => self.base_url = Some(value(arg)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// A client using the keys of every `--key-file`, or [`API_KEY_VAR`] without one, sending requests to `--base-url`
+    pub fn client(&self) -> Result<WeatherUnion, String> {
+        let keys = KeyPool::from_env_or_files(API_KEY_VAR, &self.key_files)
+            .map_err(|e| format!("{}: {:?}", e.source, e.error))?;
+        let mut client = WeatherUnion::from_pool(keys);
+        if let Some(base_url) = &self.base_url {
+            client = client.with_base_url(base_url.trim_end_matches('/'));
+        }
+        Ok(client)
+    }
+}
+
+/// The localities of a `--city` argument, an error if the city has none
+pub fn city_ids(city: &str) -> Result<Vec<LocalityId>, String> {
+    let ids: Vec<LocalityId> = LocalityId::in_city(city).collect();
+    if ids.is_empty() {
+        return Err(format!("no localities in city {}", city))
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_args() {
+        let mut args = ["--key-file", "a.txt", "--listen", "--key-file", "b.txt", "--base-url"].map(String::from).into_iter();
+        let mut client_args = ClientArgs::default();
+        let mut others = Vec::new();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+            match client_args.parse(&arg, &mut value) {
+                Ok(true) => {}
+                Ok(false) => others.push(arg),
+                Err(error) => others.push(error),
+            }
+        }
+        assert_eq!(client_args.key_files, ["a.txt", "b.txt"]);
+        assert_eq!(others, ["--listen", "--base-url needs a value"]);
+        assert!(client_args.client().err().unwrap().starts_with("a.txt: Io"));
+
+        assert!(!city_ids("Surat").unwrap().is_empty());
+        assert!(city_ids("Atlantis").is_err());
+    }
+}
@@ -0,0 +1,412 @@
+use std::io::{BufRead, Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{LocalityId, Metric, Reading};
+
+/// A reading of a locality at a point in time
+pub type Row = (LocalityId, SystemTime, Reading);
+
+/// Column names of every format, in order.\
+/// Columns are only ever appended, so readers written against an older schema keep working.
+pub const COLUMNS: [&str; 13] = [
+    "locality_id", "locality_name", "city", "latitude", "longitude", "timestamp", "device_type",
+    "temperature", "humidity", "wind_speed", "wind_direction", "rain_intensity", "rain_accumulation",
+];
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    /// `line` counts from 1, when writing it is the line the row would have been written to
+    Json { line: usize, error: serde_json::Error },
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::errors::ParquetError),
+    /// A row names a locality this build does not know
+    UnknownLocality(String),
+}
+
+/// One exported reading with its locality metadata.\
+/// Metrics the device does not report, or left out of the reading, are None and read back like the API reports them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReadingRecord {
+    pub locality_id: String,
+    pub locality_name: Option<String>,
+    pub city: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Unix milliseconds
+    pub timestamp: i64,
+    pub device_type: u8,
+    pub temperature: Option<f64>,
+    pub humidity: Option<f64>,
+    pub wind_speed: Option<f64>,
+    pub wind_direction: Option<f64>,
+    pub rain_intensity: Option<f64>,
+    pub rain_accumulation: Option<f64>,
+}
+
+impl ReadingRecord {
+
+    pub fn new(id: LocalityId, at: SystemTime, reading: &Reading) -> ReadingRecord {
+        let lat_long = id.locality_lat_long();
+        ReadingRecord {
+            locality_id: id.0.to_string(),
+            locality_name: id.locality_name().map(str::to_string),
+            city: id.city().map(str::to_string),
+            latitude: lat_long.map(|(lat, _)| lat),
+            longitude: lat_long.map(|(_, long)| long),
+            timestamp: match at.duration_since(UNIX_EPOCH) {
+                Ok(d) => d.as_millis() as i64,
+                Err(e) => -(e.duration().as_millis() as i64),
+            },
+            device_type: reading.data.device,
+            temperature: reading.metric(Metric::Temperature),
+            humidity: reading.metric(Metric::Humidity),
+            wind_speed: reading.metric(Metric::WindSpeed),
+            wind_direction: reading.metric(Metric::WindDirection),
+            rain_intensity: reading.metric(Metric::RainIntensity),
+            rain_accumulation: reading.metric(Metric::RainAccumulation),
+        }
+    }
+
+    /// The reading back, the metadata columns are ignored in favour of the built-in tables.
+    pub fn to_row(&self) -> Result<Row, ExportError> {
+        let id = LocalityId::from_str(&self.locality_id).map_err(|_| ExportError::UnknownLocality(self.locality_id.clone()))?;
+        let at = if self.timestamp >= 0 {
+            UNIX_EPOCH + Duration::from_millis(self.timestamp as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_millis(self.timestamp.unsigned_abs())
+        };
+        Ok((id, at, Reading::from_metrics(self.device_type, |metric| match metric {
+            Metric::Temperature => self.temperature,
+            Metric::Humidity => self.humidity,
+            Metric::WindSpeed => self.wind_speed,
+            Metric::WindDirection => self.wind_direction,
+            Metric::RainIntensity => self.rain_intensity,
+            Metric::RainAccumulation => self.rain_accumulation,
+        })))
+    }
+}
+
+/// Write rows as CSV with a [`COLUMNS`] header, missing values are left empty.
+/// # Example usage
+/// ```
+/// use std::time::SystemTime;
+/// use weather_union::export;
+/// use weather_union::{LocalityId, WeatherUnion};
+/// async fn example(){
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let id = LocalityId::ZWL005764;
+///     let rows = [(id, SystemTime::now(), client.locality_reading(id).await.unwrap())];
+///     export::write_csv(std::fs::File::create("readings.csv").unwrap(), &rows).unwrap();
+///     let back = export::read_csv(std::fs::File::open("readings.csv").unwrap()).unwrap();
+/// }
+/// ```
+pub fn write_csv<W: Write>(writer: W, rows: &[Row]) -> Result<(), ExportError> {
+    let mut writer = csv::Writer::from_writer(writer);
+    if rows.is_empty() {
+        writer.write_record(COLUMNS).map_err(ExportError::Csv)?;
+    }
+    for (id, at, data) in rows {
+        writer.serialize(ReadingRecord::new(*id, *at, data)).map_err(ExportError::Csv)?;
+    }
+    writer.flush().map_err(ExportError::Io)
+}
+
+/// Read rows written by [`write_csv`]
+pub fn read_csv<R: Read>(reader: R) -> Result<Vec<Row>, ExportError> {
+    csv::Reader::from_reader(reader)
+        .deserialize::<ReadingRecord>()
+        .map(|record| record.map_err(ExportError::Csv)?.to_row())
+        .collect()
+}
+
+/// Write rows as JSON Lines, one [`ReadingRecord`] object per line with null for missing values.
+pub fn write_jsonl<W: Write>(mut writer: W, rows: &[Row]) -> Result<(), ExportError> {
+    for (index, (id, at, data)) in rows.iter().enumerate() {
+        let line = serde_json::to_string(&ReadingRecord::new(*id, *at, data)).map_err(|error| ExportError::Json { line: index + 1, error })?;
+        writeln!(writer, "{}", line).map_err(ExportError::Io)?;
+    }
+    writer.flush().map_err(ExportError::Io)
+}
+
+/// Read rows written by [`write_jsonl`], blank lines are skipped.
+pub fn read_jsonl<R: BufRead>(reader: R) -> Result<Vec<Row>, ExportError> {
+    let mut rows = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(ExportError::Io)?;
+        if line.trim().is_empty() {
+            continue
+        }
+        let record: ReadingRecord = serde_json::from_str(&line).map_err(|error| ExportError::Json { line: index + 1, error })?;
+        rows.push(record.to_row()?);
+    }
+    Ok(rows)
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_format {
+    use std::sync::Arc;
+
+    use parquet::basic::Compression;
+    use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int32Type, Int64Type};
+    use parquet::errors::ParquetError;
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::reader::{ChunkReader, FileReader};
+    use parquet::file::serialized_reader::SerializedFileReader;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::record::Field;
+    use parquet::schema::parser::parse_message_type;
+
+    use super::*;
+
+    /// The [`COLUMNS`] as a Parquet schema, in the same order
+    const SCHEMA: &str = "
+        message reading {
+            REQUIRED BYTE_ARRAY locality_id (UTF8);
+            OPTIONAL BYTE_ARRAY locality_name (UTF8);
+            OPTIONAL BYTE_ARRAY city (UTF8);
+            OPTIONAL DOUBLE latitude;
+            OPTIONAL DOUBLE longitude;
+            REQUIRED INT64 timestamp (TIMESTAMP(MILLIS, true));
+            REQUIRED INT32 device_type (INTEGER(8, false));
+            OPTIONAL DOUBLE temperature;
+            OPTIONAL DOUBLE humidity;
+            OPTIONAL DOUBLE wind_speed;
+            OPTIONAL DOUBLE wind_direction;
+            OPTIONAL DOUBLE rain_intensity;
+            OPTIONAL DOUBLE rain_accumulation;
+        }
+    ";
+
+    /// Values of an optional column and its definition levels, 0 where the value is missing
+    fn optional<T: Clone>(values: impl Iterator<Item = Option<T>>) -> (Vec<T>, Vec<i16>) {
+        let mut present = Vec::new();
+        let levels = values.map(|value| match value {
+            Some(value) => {
+                present.push(value);
+                1
+            }
+            None => 0,
+        }).collect();
+        (present, levels)
+    }
+
+    fn strings(values: impl Iterator<Item = Option<String>>) -> (Vec<ByteArray>, Vec<i16>) {
+        optional(values.map(|value| value.map(|s| ByteArray::from(s.into_bytes()))))
+    }
+
+    /// Write rows as a Snappy compressed Parquet file with a single row group, built with `--features parquet`.
+    /// # Example usage
+    /// ```
+    /// use std::time::SystemTime;
+    /// use weather_union::export;
+    /// use weather_union::{LocalityId, WeatherUnion};
+    /// async fn example(){
+    ///     let client = WeatherUnion::from_key("api_key".to_string());
+    ///     let id = LocalityId::ZWL005764;
+    ///     let rows = [(id, SystemTime::now(), client.locality_reading(id).await.unwrap())];
+    ///     export::write_parquet(std::fs::File::create("readings.parquet").unwrap(), &rows).unwrap();
+    ///     let back = export::read_parquet(std::fs::File::open("readings.parquet").unwrap()).unwrap();
+    /// }
+    /// ```
+    pub fn write_parquet<W: Write + Send>(writer: W, rows: &[Row]) -> Result<(), ExportError> {
+        let schema = Arc::new(parse_message_type(SCHEMA).map_err(ExportError::Parquet)?);
+        let names: Vec<String> = schema.get_fields().iter().map(|field| field.name().to_string()).collect();
+        let properties = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+        let mut file = SerializedFileWriter::new(writer, schema, properties).map_err(ExportError::Parquet)?;
+        let records: Vec<ReadingRecord> = rows.iter().map(|(id, at, data)| ReadingRecord::new(*id, *at, data)).collect();
+
+        let mut group = file.next_row_group().map_err(ExportError::Parquet)?;
+        let mut index = 0;
+        while let Some(mut column) = group.next_column().map_err(ExportError::Parquet)? {
+            let written = match names[index].as_str() {
+                "locality_id" => {
+                    let (values, _) = strings(records.iter().map(|r| Some(r.locality_id.clone())));
+                    column.typed::<ByteArrayType>().write_batch(&values, None, None)
+                }
+                "locality_name" | "city" => {
+                    let city = names[index] == "city";
+                    let (values, levels) = strings(records.iter().map(|r| if city { r.city.clone() } else { r.locality_name.clone() }));
+                    column.typed::<ByteArrayType>().write_batch(&values, Some(&levels), None)
+                }
+                "timestamp" => {
+                    let values: Vec<i64> = records.iter().map(|r| r.timestamp).collect();
+                    column.typed::<Int64Type>().write_batch(&values, None, None)
+                }
+                "device_type" => {
+                    let values: Vec<i32> = records.iter().map(|r| r.device_type as i32).collect();
+                    column.typed::<Int32Type>().write_batch(&values, None, None)
+                }
+                name => {
+                    let (values, levels) = optional(records.iter().map(|r| double_column(r, name)));
+                    column.typed::<DoubleType>().write_batch(&values, Some(&levels), None)
+                }
+            };
+            written.map_err(ExportError::Parquet)?;
+            column.close().map_err(ExportError::Parquet)?;
+            index += 1;
+        }
+        group.close().map_err(ExportError::Parquet)?;
+        file.close().map_err(ExportError::Parquet)?;
+        Ok(())
+    }
+
+    fn double_column(record: &ReadingRecord, name: &str) -> Option<f64> {
+        match name {
+            "latitude" => record.latitude,
+            "longitude" => record.longitude,
+            "temperature" => record.temperature,
+            "humidity" => record.humidity,
+            "wind_speed" => record.wind_speed,
+            "wind_direction" => record.wind_direction,
+            "rain_intensity" => record.rain_intensity,
+            "rain_accumulation" => record.rain_accumulation,
+            _ => None,
+        }
+    }
+
+    fn double(field: &Field) -> Option<f64> {
+        match field {
+            Field::Double(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn string(field: &Field) -> Option<String> {
+        match field {
+            Field::Str(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    /// Read rows written by [`write_parquet`] from a `File` or anything else Parquet can seek in.\
+    /// Columns are matched by name, so files with extra columns can be read.
+    pub fn read_parquet<R: ChunkReader + 'static>(reader: R) -> Result<Vec<Row>, ExportError> {
+        let file = SerializedFileReader::new(reader).map_err(ExportError::Parquet)?;
+        let mut rows = Vec::new();
+        for row in file.get_row_iter(None).map_err(ExportError::Parquet)? {
+            let row = row.map_err(ExportError::Parquet)?;
+            let mut record = ReadingRecord::default();
+            for (name, field) in row.get_column_iter() {
+                match (name.as_str(), field) {
+                    ("locality_id", _) => record.locality_id = string(field).unwrap_or_default(),
+                    ("locality_name", _) => record.locality_name = string(field),
+                    ("city", _) => record.city = string(field),
+                    ("timestamp", Field::TimestampMillis(millis) | Field::Long(millis)) => record.timestamp = *millis,
+                    ("device_type", Field::UByte(device)) => record.device_type = *device,
+                    ("timestamp" | "device_type", _) => return Err(ExportError::Parquet(ParquetError::General(
+                        format!("{} column holds {:?}", name, field)
+                    ))),
+                    ("latitude", _) => record.latitude = double(field),
+                    ("longitude", _) => record.longitude = double(field),
+                    ("temperature", _) => record.temperature = double(field),
+                    ("humidity", _) => record.humidity = double(field),
+                    ("wind_speed", _) => record.wind_speed = double(field),
+                    ("wind_direction", _) => record.wind_direction = double(field),
+                    ("rain_intensity", _) => record.rain_intensity = double(field),
+                    ("rain_accumulation", _) => record.rain_accumulation = double(field),
+                    _ => {}
+                }
+            }
+            rows.push(record.to_row()?);
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(feature = "parquet")]
+pub use parquet_format::{read_parquet, write_parquet};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aws, rgs, LocalityWeatherData};
+
+    fn rows() -> Vec<Row> {
+        let at = UNIX_EPOCH + Duration::from_millis(1_704_067_200_250);
+        vec![
+            (LocalityId::ZWL005764, at, LocalityWeatherData { wind_speed: 1.5, rain_accumulation: 2.0, ..aws(24.5) }.into()),
+            (LocalityId::ZWL008436, at + Duration::from_secs(60), rgs(0.25, 3.5).into()),
+        ]
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &rows()).unwrap();
+        let text = String::from_utf8(csv.clone()).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next().unwrap(), COLUMNS.join(","));
+        assert_eq!(
+            lines.next().unwrap(),
+            "ZWL005764,Delhi NCR Sarita Vihar,Delhi NCR,28.531759,77.293973,1704067200250,1,24.5,60.0,1.5,90.0,0.0,2.0"
+        );
+        // A rain gauge has no temperature, humidity or wind
+        assert!(lines.next().unwrap().ends_with(",2,,,,,0.25,3.5"));
+        assert_eq!(read_csv(csv.as_slice()).unwrap(), rows());
+
+        let mut empty = Vec::new();
+        write_csv(&mut empty, &[]).unwrap();
+        assert!(read_csv(empty.as_slice()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let mut jsonl = Vec::new();
+        write_jsonl(&mut jsonl, &rows()).unwrap();
+        let first: serde_json::Value = serde_json::from_slice(jsonl.split(|b| *b == b'\n').next().unwrap()).unwrap();
+        let keys: Vec<&str> = first.as_object().unwrap().keys().map(String::as_str).collect();
+        assert_eq!(keys.len(), COLUMNS.len());
+        assert!(COLUMNS.iter().all(|column| keys.contains(column)));
+        assert_eq!(read_jsonl(jsonl.as_slice()).unwrap(), rows());
+
+        let unknown = String::from_utf8(jsonl).unwrap().replace("ZWL008436", "ZWL000000");
+        assert!(matches!(read_jsonl(unknown.as_bytes()), Err(ExportError::UnknownLocality(id)) if id == "ZWL000000"));
+        assert!(matches!(read_jsonl(&b"\n{\"locality_id\":"[..]), Err(ExportError::Json { line: 2, .. })));
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_round_trip() {
+        use parquet::file::reader::FileReader;
+        use parquet::file::serialized_reader::SerializedFileReader;
+
+        let path = std::env::temp_dir().join(format!("weather_union_export_{}.parquet", std::process::id()));
+        write_parquet(std::fs::File::create(&path).unwrap(), &rows()).unwrap();
+        let back = read_parquet(std::fs::File::open(&path).unwrap());
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(back.unwrap(), rows());
+        let names: Vec<&str> = reader.metadata().file_metadata().schema_descr().columns().iter().map(|c| c.name()).collect();
+        assert_eq!(names, COLUMNS);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_device_type_must_be_a_byte() {
+        use std::sync::Arc;
+        use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+        use parquet::errors::ParquetError;
+        use parquet::file::writer::SerializedFileWriter;
+        use parquet::schema::parser::parse_message_type;
+
+        let path = std::env::temp_dir().join(format!("weather_union_device_type_{}.parquet", std::process::id()));
+        let schema = "message reading { REQUIRED BYTE_ARRAY locality_id (UTF8); REQUIRED INT64 device_type; }";
+        let mut file = SerializedFileWriter::new(std::fs::File::create(&path).unwrap(), Arc::new(parse_message_type(schema).unwrap()), Default::default()).unwrap();
+        let mut group = file.next_row_group().unwrap();
+        let mut column = group.next_column().unwrap().unwrap();
+        column.typed::<ByteArrayType>().write_batch(&[ByteArray::from("ZWL005764")], None, None).unwrap();
+        column.close().unwrap();
+        let mut column = group.next_column().unwrap().unwrap();
+        column.typed::<Int64Type>().write_batch(&[300], None, None).unwrap();
+        column.close().unwrap();
+        group.close().unwrap();
+        file.close().unwrap();
+        let back = read_parquet(std::fs::File::open(&path).unwrap());
+        let _ = std::fs::remove_file(&path);
+        assert!(matches!(back, Err(ExportError::Parquet(ParquetError::General(message))) if message.contains("device_type")));
+    }
+}
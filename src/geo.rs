@@ -0,0 +1,17 @@
+/// Mean radius of the earth in kilometres
+pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// Great circle distance in kilometres between two (lat, long) points in degrees
+/// # Example
+/// ```
+///  use weather_union::geo::haversine_km;
+///  let distance = haversine_km((28.531759, 77.293973), (28.460895, 77.304764));
+///  assert!((distance - 7.95).abs() < 0.01);
+/// ```
+pub fn haversine_km(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat_a, lat_b) = (a.0.to_radians(), b.0.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_long = (b.1 - a.1).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_long / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+}
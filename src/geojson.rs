@@ -0,0 +1,122 @@
+use serde_json::{json, Map, Value};
+
+use crate::{LocalityId, Metric, Reading};
+
+#[derive(Debug)]
+pub enum GeoJsonError {
+    Json(serde_json::Error), NotAFeatureCollection, MissingId(usize), UnknownLocality(String)
+}
+
+fn locality_feature(id: LocalityId) -> Option<Map<String, Value>> {
+    let (lat, long) = id.locality_lat_long()?;
+    let mut feature = Map::new();
+    feature.insert("type".into(), json!("Feature"));
+    feature.insert("id".into(), json!(id.0));
+    feature.insert("geometry".into(), json!({ "type": "Point", "coordinates": [long, lat] }));
+    feature.insert("properties".into(), json!({
+        "id": id.0,
+        "name": id.locality_name(),
+        "city": id.city(),
+        "device_type": id.device_type(),
+    }));
+    Some(feature)
+}
+
+/// FeatureCollection with a point per locality, localities without coordinates are skipped.
+/// # Example
+/// ```
+///  use weather_union::geojson;
+///  use weather_union::LocalityId;
+///  let collection = geojson::localities([LocalityId::ZWL005764]);
+///  assert_eq!(collection["features"][0]["properties"]["city"], "Delhi NCR");
+/// ```
+pub fn localities(ids: impl IntoIterator<Item = LocalityId>) -> Value {
+    let features: Vec<Value> = ids.into_iter().filter_map(locality_feature).map(Value::Object).collect();
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+/// FeatureCollection of every built-in locality
+pub fn catalogue() -> Value {
+    localities(LocalityId::ALL.iter().copied())
+}
+
+/// FeatureCollection with a point per reading, carrying the locality properties and one property per metric.\
+/// Metrics the device does not report are null.
+/// # Example usage
+/// ```
+/// use weather_union::{geojson, LocalityId, WeatherUnion};
+/// async fn example(){
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let id = LocalityId::ZWL005764;
+///     let data = client.locality_reading(id).await.unwrap();
+///     println!("{}", geojson::readings(&[(id, data)]));
+/// }
+/// ```
+pub fn readings<'a>(readings: impl IntoIterator<Item = &'a (LocalityId, Reading)>) -> Value {
+    let features: Vec<Value> = readings.into_iter().filter_map(|(id, data)| {
+        let mut feature = locality_feature(*id)?;
+        if let Some(Value::Object(properties)) = feature.get_mut("properties") {
+            for metric in Metric::ALL {
+                properties.insert(metric.name().into(), json!(data.metric(metric)));
+            }
+        }
+        Some(Value::Object(feature))
+    }).collect();
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+/// Read the localities of a FeatureCollection, as written by [`localities`] or [`readings`].\
+/// Each feature is identified by its `id` member, or an `id` property.
+/// # Example
+/// ```
+///  use weather_union::geojson;
+///  use weather_union::LocalityId;
+///  let collection = geojson::localities([LocalityId::ZWL005764, LocalityId::ZWL008752]);
+///  let ids = geojson::parse_localities(&collection.to_string()).unwrap();
+///  assert_eq!(ids, vec![LocalityId::ZWL005764, LocalityId::ZWL008752]);
+/// ```
+pub fn parse_localities(source: &str) -> Result<Vec<LocalityId>, GeoJsonError> {
+    let value: Value = serde_json::from_str(source).map_err(GeoJsonError::Json)?;
+    if value["type"] != "FeatureCollection" {
+        return Err(GeoJsonError::NotAFeatureCollection)
+    }
+    let features = value["features"].as_array().ok_or(GeoJsonError::NotAFeatureCollection)?;
+    features.iter().enumerate().map(|(index, feature)| {
+        let id = feature["id"].as_str()
+            .or_else(|| feature["properties"]["id"].as_str())
+            .ok_or(GeoJsonError::MissingId(index))?;
+        LocalityId::from_str(id).map_err(|_| GeoJsonError::UnknownLocality(id.to_string()))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalogue() {
+        let catalogue = catalogue();
+        let features = catalogue["features"].as_array().unwrap();
+        assert_eq!(features.len(), LocalityId::ALL.len());
+        assert_eq!(features[0]["geometry"]["coordinates"], json!([77.293973, 28.531759]));
+        assert_eq!(parse_localities(&catalogue.to_string()).unwrap(), LocalityId::ALL);
+    }
+
+    #[test]
+    fn test_readings() {
+        let data = Reading::from(crate::rgs(1.5, 12.0));
+        let collection = readings(&[(LocalityId::ZWL005764, data)]);
+        let properties = &collection["features"][0]["properties"];
+        assert_eq!(properties["rain_accumulation"], 12.0);
+        assert!(properties["temperature"].is_null());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(parse_localities("[]"), Err(GeoJsonError::NotAFeatureCollection)));
+        assert!(matches!(parse_localities(r#"{"type": "FeatureCollection", "features": [{"properties": {}}]}"#),
+            Err(GeoJsonError::MissingId(0))));
+        assert!(matches!(parse_localities(r#"{"type": "FeatureCollection", "features": [{"id": "ZWL000000"}]}"#),
+            Err(GeoJsonError::UnknownLocality(_))));
+    }
+}
@@ -0,0 +1,262 @@
+use serde_json::{json, Value};
+
+use crate::interpolate::Interpolator;
+use crate::{LocalityId, Metric};
+
+/// A lat/long rectangle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl BoundingBox {
+
+    /// Smallest box containing every locality with known coordinates, None if there are none.
+    pub fn around(ids: impl IntoIterator<Item = LocalityId>) -> Option<BoundingBox> {
+        ids.into_iter().filter_map(|id| id.locality_lat_long()).fold(None, |bbox, (lat, long)| {
+            Some(match bbox {
+                None => BoundingBox { south: lat, west: long, north: lat, east: long },
+                Some(b) => BoundingBox {
+                    south: b.south.min(lat), west: b.west.min(long),
+                    north: b.north.max(lat), east: b.east.max(long),
+                },
+            })
+        })
+    }
+
+    /// Box around every locality of a city
+    /// # Example
+    /// ```
+    ///  use weather_union::heatmap::BoundingBox;
+    ///  let bbox = BoundingBox::city("Mysore").unwrap();
+    ///  assert_eq!(bbox.north, bbox.south); // a single locality
+    /// ```
+    pub fn city(city: &str) -> Option<BoundingBox> {
+        BoundingBox::around(LocalityId::in_city(city))
+    }
+
+    /// Grow the box by `degrees` on every side.
+    pub fn pad(self, degrees: f64) -> BoundingBox {
+        BoundingBox {
+            south: self.south - degrees, west: self.west - degrees,
+            north: self.north + degrees, east: self.east + degrees,
+        }
+    }
+
+    pub fn contains(&self, (lat, long): (f64, f64)) -> bool {
+        lat >= self.south && lat <= self.north && long >= self.west && long <= self.east
+    }
+}
+
+/// A regular grid of interpolated values of one metric.\
+/// Row 0 is the northern edge and column 0 the western edge.
+/// # Example usage
+/// ```
+/// use weather_union::heatmap::{BoundingBox, Grid};
+/// use weather_union::interpolate::Interpolator;
+/// use weather_union::{LocalityId, Metric, WeatherUnion};
+/// async fn example(){
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let mut readings = Vec::new();
+///     for id in LocalityId::in_city("Bengaluru") {
+///         if let Ok(data) = client.locality_reading(id).await {
+///             readings.push((id, data));
+///         }
+///     }
+///     let bbox = BoundingBox::city("Bengaluru").unwrap().pad(0.02);
+///     let grid = Grid::new(&Interpolator::new(&readings), Metric::Temperature, bbox, 40, 40);
+///     println!("{}", grid.to_ascii());
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grid {
+    pub metric: Metric,
+    pub bbox: BoundingBox,
+    pub rows: usize,
+    pub cols: usize,
+    cells: Vec<Option<f64>>,
+}
+
+impl Grid {
+
+    /// Interpolate `metric` at the centre of every cell, cells out of reach of any station are left empty.
+    pub fn new(interpolator: &Interpolator, metric: Metric, bbox: BoundingBox, rows: usize, cols: usize) -> Grid {
+        let (rows, cols) = (rows.max(1), cols.max(1));
+        let mut grid = Grid { metric, bbox, rows, cols, cells: Vec::with_capacity(rows * cols) };
+        for row in 0..rows {
+            for col in 0..cols {
+                let estimate = interpolator.estimate(metric, grid.cell_center(row, col));
+                grid.cells.push(estimate.map(|e| e.value));
+            }
+        }
+        grid
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        ((self.bbox.north - self.bbox.south) / self.rows as f64, (self.bbox.east - self.bbox.west) / self.cols as f64)
+    }
+
+    /// (lat, long) of the centre of a cell
+    pub fn cell_center(&self, row: usize, col: usize) -> (f64, f64) {
+        let (height, width) = self.cell_size();
+        (self.bbox.north - height * (row as f64 + 0.5), self.bbox.west + width * (col as f64 + 0.5))
+    }
+
+    pub fn value(&self, row: usize, col: usize) -> Option<f64> {
+        self.cells[row * self.cols + col]
+    }
+
+    /// Lowest and highest values in the grid
+    pub fn range(&self) -> Option<(f64, f64)> {
+        self.cells.iter().flatten().fold(None, |range, v| match range {
+            None => Some((*v, *v)),
+            Some((low, high)) => Some((v.min(low), v.max(high))),
+        })
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+        (0..self.rows).flat_map(move |row| (0..self.cols).map(move |col| (row, col)))
+            .filter_map(|(row, col)| Some((row, col, self.value(row, col)?)))
+    }
+
+    /// GeoJSON FeatureCollection with a polygon per non empty cell, the value is stored under the metric name.
+    pub fn to_geojson(&self) -> Value {
+        let (height, width) = self.cell_size();
+        let features: Vec<Value> = self.cells().map(|(row, col, value)| {
+            let north = self.bbox.north - height * row as f64;
+            let west = self.bbox.west + width * col as f64;
+            let (south, east) = (north - height, west + width);
+            json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[west, north], [east, north], [east, south], [west, south], [west, north]]],
+                },
+                "properties": { "row": row, "col": col, self.metric.name(): value },
+            })
+        }).collect();
+        json!({ "type": "FeatureCollection", "features": features })
+    }
+
+    /// CSV with a `lat,long,<metric>` header and a line per non empty cell centre.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("lat,long,{}\n", self.metric.name());
+        for (row, col, value) in self.cells() {
+            let (lat, long) = self.cell_center(row, col);
+            csv.push_str(&format!("{lat:.6},{long:.6},{value}\n"));
+        }
+        csv
+    }
+
+    /// One character per cell from `.:-=+*#%@` (low to high), empty cells are blank.
+    pub fn to_ascii(&self) -> String {
+        const RAMP: &[u8] = b".:-=+*#%@";
+        let range = self.range();
+        let mut ascii = String::with_capacity((self.cols + 1) * self.rows);
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                ascii.push(match self.value(row, col) {
+                    Some(value) => RAMP[(scale(range, value) * (RAMP.len() - 1) as f64).round() as usize] as char,
+                    None => ' ',
+                });
+            }
+            ascii.push('\n');
+        }
+        ascii
+    }
+
+    /// PNG image with a pixel per cell coloured blue (low) to red (high), empty cells are transparent.
+    #[cfg(feature = "png")]
+    pub fn to_png(&self) -> Vec<u8> {
+        let range = self.range();
+        let mut pixels = Vec::with_capacity(self.rows * self.cols * 4);
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                match self.value(row, col) {
+                    Some(value) => {
+                        let t = scale(range, value);
+                        pixels.extend_from_slice(&[(255.0 * t) as u8, (255.0 * (1.0 - (2.0 * t - 1.0).abs())) as u8, (255.0 * (1.0 - t)) as u8, 255]);
+                    }
+                    None => pixels.extend_from_slice(&[0, 0, 0, 0]),
+                }
+            }
+        }
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, self.cols as u32, self.rows as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        // Writing to a Vec cannot fail and the dimensions always match the pixel buffer
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        drop(writer);
+        out
+    }
+}
+
+/// Position of a value within a grid's range, from 0.0 to 1.0
+fn scale(range: Option<(f64, f64)>, value: f64) -> f64 {
+    match range {
+        Some((low, high)) if high > low => ((value - low) / (high - low)).clamp(0.0, 1.0),
+        _ => 0.5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws;
+
+    fn grid() -> Grid {
+        let readings = [(LocalityId::ZWL005764, aws(30.0).into()), (LocalityId::ZWL005996, aws(32.0).into())];
+        let bbox = BoundingBox::around(readings.iter().map(|r| r.0)).unwrap().pad(0.01);
+        Grid::new(&Interpolator::new(&readings).max_distance_km(20.0), Metric::Temperature, bbox, 4, 5)
+    }
+
+    #[test]
+    fn test_grid_exports() {
+        let grid = grid();
+        let (low, high) = grid.range().unwrap();
+        assert!(low >= 30.0 && high <= 32.0 && low < high);
+        assert_eq!(grid.to_geojson()["features"].as_array().unwrap().len(), 20);
+        assert_eq!(grid.to_csv().lines().count(), 21);
+        let ascii = grid.to_ascii();
+        assert_eq!(ascii.lines().count(), 4);
+        assert!(ascii.lines().all(|line| line.len() == 5));
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let bbox = BoundingBox::city("Delhi NCR").unwrap();
+        assert!(LocalityId::in_city("Delhi NCR").all(|id| bbox.contains(id.locality_lat_long().unwrap())));
+        assert!(!bbox.contains((12.936787, 77.556079)));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_png() {
+        let grid = grid();
+        let png = grid.to_png();
+        let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (5, 4));
+        assert_eq!((info.color_type, info.bit_depth), (png::ColorType::Rgba, png::BitDepth::Eight));
+
+        let (low, high) = grid.range().unwrap();
+        let pixel = |row: usize, col: usize| &pixels[(row * grid.cols + col) * 4..][..4];
+        for row in 0..grid.rows {
+            for col in 0..grid.cols {
+                match grid.value(row, col) {
+                    // The coldest cell is pure blue and the warmest pure red
+                    Some(value) if value == low => assert_eq!(pixel(row, col), [0, 0, 255, 255]),
+                    Some(value) if value == high => assert_eq!(pixel(row, col), [255, 0, 0, 255]),
+                    Some(_) => assert_eq!(pixel(row, col)[3], 255),
+                    None => assert_eq!(pixel(row, col), [0, 0, 0, 0]),
+                }
+            }
+        }
+    }
+}
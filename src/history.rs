@@ -0,0 +1,286 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures_util::{Stream, StreamExt};
+
+use crate::{LocalityId, Reading, WeatherResponseError};
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteHistory;
+
+#[derive(Debug)]
+pub enum HistoryError {
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
+    /// The time is before the unix epoch or too far ahead to store
+    InvalidTime(SystemTime),
+}
+
+/// How much history to keep per locality, see [`HistoryStore::prune`]
+/// # Example
+/// ```
+///  use std::time::Duration;
+///  use weather_union::history::Retention;
+///  // A week, and at most one reading a minute over that week
+///  let retention = Retention::new().max_age(Duration::from_secs(7 * 86400)).max_readings(7 * 1440);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Retention {
+    pub max_age: Option<Duration>,
+    pub max_readings: Option<usize>,
+}
+
+impl Retention {
+
+    /// Keep everything
+    pub fn new() -> Retention {
+        Retention::default()
+    }
+
+    /// Drop readings older than `max_age`
+    pub fn max_age(mut self, max_age: Duration) -> Retention {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Keep only the newest `max_readings` readings of each locality
+    pub fn max_readings(mut self, max_readings: usize) -> Retention {
+        self.max_readings = Some(max_readings);
+        self
+    }
+}
+
+/// Timestamped readings per locality.\
+/// A reading identical to the one before it is not stored, so polling an idle station costs nothing,
+/// and a second reading at the same time replaces the first.
+/// # Example usage
+/// ```
+/// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+/// use weather_union::history::{HistoryStore, MemoryHistory};
+/// use weather_union::{LocalityId, WeatherUnion};
+/// async fn example(){
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let mut history = MemoryHistory::new();
+///     let id = LocalityId::ZWL005764;
+///     history.append(id, SystemTime::now(), client.locality_reading(id).await.unwrap()).unwrap();
+///     let now = SystemTime::now();
+///     let last_hour = history.range(id, now - Duration::from_secs(3600), now).unwrap();
+/// }
+/// ```
+pub trait HistoryStore {
+
+    /// Store a reading, returns false if it repeats the previous reading of the locality.\
+    /// Times are kept to the millisecond, a reading in the same millisecond as a stored one replaces it.
+    fn append(&mut self, id: LocalityId, at: SystemTime, reading: Reading) -> Result<bool, HistoryError>;
+
+    /// Readings of a locality taken in `[since, until]`, oldest first
+    fn range(&self, id: LocalityId, since: SystemTime, until: SystemTime) -> Result<Vec<(SystemTime, Reading)>, HistoryError>;
+
+    /// The newest reading of a locality
+    fn latest(&self, id: LocalityId) -> Result<Option<(SystemTime, Reading)>, HistoryError>;
+
+    /// Drop readings outside the retention policy, returns how many were dropped.
+    fn prune(&mut self, retention: Retention, now: SystemTime) -> Result<usize, HistoryError>;
+}
+
+impl<S: HistoryStore + ?Sized> HistoryStore for Box<S> {
+    fn append(&mut self, id: LocalityId, at: SystemTime, reading: Reading) -> Result<bool, HistoryError> {
+        (**self).append(id, at, reading)
+    }
+
+    fn range(&self, id: LocalityId, since: SystemTime, until: SystemTime) -> Result<Vec<(SystemTime, Reading)>, HistoryError> {
+        (**self).range(id, since, until)
+    }
+
+    fn latest(&self, id: LocalityId) -> Result<Option<(SystemTime, Reading)>, HistoryError> {
+        (**self).latest(id)
+    }
+
+    fn prune(&mut self, retention: Retention, now: SystemTime) -> Result<usize, HistoryError> {
+        (**self).prune(retention, now)
+    }
+}
+
+/// Append every successful reading of a stream such as [`WeatherUnion::watch`](crate::WeatherUnion::watch),
+/// stamped with the time it arrived.\
+/// Runs until the stream ends or the store fails, returns the number of readings stored.
+/// # Example usage
+/// ```
+/// use std::time::Duration;
+/// use weather_union::history::{record, MemoryHistory};
+/// use weather_union::{LocalityId, WeatherUnion};
+/// async fn example(){
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let mut history = MemoryHistory::new();
+///     let readings = client.watch(LocalityId::in_city("Surat"), Duration::from_secs(600));
+///     record(&mut history, readings).await.unwrap();
+/// }
+/// ```
+pub async fn record<S: HistoryStore + ?Sized>(
+    store: &mut S,
+    readings: impl Stream<Item = (LocalityId, Result<Reading, WeatherResponseError>)>,
+) -> Result<u64, HistoryError> {
+    let mut readings = std::pin::pin!(readings);
+    let mut stored = 0;
+    while let Some((id, result)) = readings.next().await {
+        if let Ok(data) = result {
+            if store.append(id, SystemTime::now(), data)? {
+                stored += 1;
+            }
+        }
+    }
+    Ok(stored)
+}
+
+/// Unix milliseconds of a time, the precision readings are stored at
+fn millis(at: SystemTime) -> Result<i64, HistoryError> {
+    at.duration_since(UNIX_EPOCH).ok()
+        .and_then(|d| i64::try_from(d.as_millis()).ok())
+        .ok_or(HistoryError::InvalidTime(at))
+}
+
+/// Readings kept in memory, lost when the process exits.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryHistory {
+    readings: HashMap<LocalityId, VecDeque<(SystemTime, Reading)>>,
+}
+
+impl MemoryHistory {
+
+    pub fn new() -> MemoryHistory {
+        MemoryHistory::default()
+    }
+}
+
+impl HistoryStore for MemoryHistory {
+    fn append(&mut self, id: LocalityId, at: SystemTime, reading: Reading) -> Result<bool, HistoryError> {
+        // Same precision and range as SqliteHistory, which keys readings on unix milliseconds
+        let at = UNIX_EPOCH + Duration::from_millis(millis(at)? as u64);
+        let readings = self.readings.entry(id).or_default();
+        let index = match readings.binary_search_by_key(&at, |(time, _)| *time) {
+            Ok(existing) => {
+                let changed = readings[existing].1 != reading;
+                readings[existing].1 = reading;
+                return Ok(changed)
+            }
+            Err(index) => index,
+        };
+        if index > 0 && readings[index - 1].1 == reading {
+            return Ok(false)
+        }
+        readings.insert(index, (at, reading));
+        Ok(true)
+    }
+
+    fn range(&self, id: LocalityId, since: SystemTime, until: SystemTime) -> Result<Vec<(SystemTime, Reading)>, HistoryError> {
+        Ok(self.readings.get(&id)
+            .map(|readings| readings.iter().filter(|(at, _)| *at >= since && *at <= until).copied().collect())
+            .unwrap_or_default())
+    }
+
+    fn latest(&self, id: LocalityId) -> Result<Option<(SystemTime, Reading)>, HistoryError> {
+        Ok(self.readings.get(&id).and_then(|readings| readings.back().copied()))
+    }
+
+    fn prune(&mut self, retention: Retention, now: SystemTime) -> Result<usize, HistoryError> {
+        let mut dropped = 0;
+        for readings in self.readings.values_mut() {
+            let before = readings.len();
+            if let Some(cutoff) = retention.max_age.and_then(|age| now.checked_sub(age)) {
+                readings.retain(|(at, _)| *at >= cutoff);
+            }
+            if let Some(max) = retention.max_readings {
+                readings.drain(..readings.len().saturating_sub(max));
+            }
+            dropped += before - readings.len();
+        }
+        self.readings.retain(|_, readings| !readings.is_empty());
+        Ok(dropped)
+    }
+}
+
+/// Shared checks run against every [`HistoryStore`] implementation
+#[cfg(test)]
+pub(crate) fn check_store(store: &mut dyn HistoryStore) {
+    use std::time::UNIX_EPOCH;
+    use crate::Metric;
+
+    let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+    let aws = |temperature| Reading::from(crate::aws(temperature));
+    let id = LocalityId::ZWL005764;
+    assert!(store.append(id, at(20), aws(20.0)).unwrap());
+    assert!(store.append(id, at(10), aws(10.0)).unwrap());
+    // Identical to the reading before it
+    assert!(!store.append(id, at(25), aws(20.0)).unwrap());
+    assert!(store.append(id, at(30), aws(30.0)).unwrap());
+    assert!(store.append(id, at(30), aws(31.0)).unwrap());
+    // Repeating a stored reading at its time, out of order or within the same millisecond, changes nothing
+    assert!(!store.append(id, at(20), aws(20.0)).unwrap());
+    assert!(!store.append(id, at(30) + Duration::from_micros(500), aws(31.0)).unwrap());
+    assert!(matches!(store.append(id, UNIX_EPOCH - Duration::from_secs(1), aws(0.0)), Err(HistoryError::InvalidTime(_))));
+    assert!(store.append(id, at(40), aws(40.0)).unwrap());
+    assert!(store.append(LocalityId::ZWL008436, at(40), aws(0.0)).unwrap());
+    assert_eq!(store.latest(id).unwrap(), Some((at(40), aws(40.0))));
+
+    let temperatures = |store: &dyn HistoryStore, since, until| -> Vec<f64> {
+        store.range(id, at(since), at(until)).unwrap().iter().map(|(_, r)| r.data.temperature).collect()
+    };
+    assert_eq!(temperatures(store, 0, 100), [10.0, 20.0, 31.0, 40.0]);
+    assert_eq!(temperatures(store, 20, 30), [20.0, 31.0]);
+    assert_eq!(store.range(id, at(30), at(30)).unwrap(), [(at(30), aws(31.0))]);
+    assert!(store.range(LocalityId::ZWL008752, at(0), at(100)).unwrap().is_empty());
+    assert_eq!(store.latest(LocalityId::ZWL008752).unwrap(), None);
+
+    assert_eq!(store.prune(Retention::new().max_age(Duration::from_secs(25)), at(50)).unwrap(), 2);
+    assert_eq!(temperatures(store, 0, 100), [31.0, 40.0]);
+    assert_eq!(store.prune(Retention::new().max_readings(1), at(50)).unwrap(), 1);
+    assert_eq!(temperatures(store, 0, 100), [40.0]);
+    assert_eq!(store.latest(LocalityId::ZWL008436).unwrap(), Some((at(40), aws(0.0))));
+
+    // Bounds that cannot be stored still keep their side of the range
+    let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+    let far_future = UNIX_EPOCH + Duration::from_secs(1 << 62);
+    assert!(store.range(id, before_epoch - Duration::from_secs(1), before_epoch).unwrap().is_empty());
+    assert!(store.range(id, far_future, far_future + Duration::from_secs(1)).unwrap().is_empty());
+    assert_eq!(store.range(id, before_epoch, far_future).unwrap(), [(at(40), aws(40.0))]);
+    assert_eq!(store.prune(Retention::new().max_age(Duration::from_secs(1)), far_future).unwrap(), 2);
+    assert_eq!(store.latest(id).unwrap(), None);
+
+    // Metrics left out of a reading are still missing when read back
+    let gap = Reading::from_metrics(1, |metric| (metric != Metric::Humidity).then_some(1.0));
+    assert!(store.append(id, at(60), gap).unwrap());
+    let (_, stored) = store.latest(id).unwrap().unwrap();
+    assert_eq!(stored, gap);
+    assert_eq!(stored.metric(Metric::Humidity), None);
+    // and NaN is NaN rather than missing
+    let nan = Reading::from(crate::aws(f64::NAN));
+    assert!(store.append(id, at(70), nan).unwrap());
+    let (_, stored) = store.latest(id).unwrap().unwrap();
+    assert!(stored.metric(Metric::Temperature).is_some_and(f64::is_nan));
+    assert_eq!(stored.data.humidity, nan.data.humidity);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_history() {
+        check_store(&mut MemoryHistory::new());
+    }
+
+    #[test]
+    fn test_record_stream() {
+        let data = Reading::from(crate::rgs(0.5, 3.0));
+        let readings = futures_util::stream::iter([
+            (LocalityId::ZWL008436, Ok(data)),
+            (LocalityId::ZWL008436, Err(WeatherResponseError::ApiKeyLimitExhausted)),
+            (LocalityId::ZWL008436, Ok(data)),
+        ]);
+        let mut history = MemoryHistory::new();
+        assert_eq!(tokio_test::block_on(record(&mut history, readings)).unwrap(), 1);
+        assert_eq!(history.latest(LocalityId::ZWL008436).unwrap().unwrap().1, data);
+    }
+}
@@ -0,0 +1,195 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::types::{FromSql, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Row, ToSql};
+
+use super::{millis, HistoryError, HistoryStore, Retention};
+use crate::{LocalityId, Metric, Reading};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS readings (
+    locality TEXT NOT NULL,
+    time_ms INTEGER NOT NULL,
+    device INTEGER NOT NULL,
+    temperature REAL,
+    humidity REAL,
+    wind_speed REAL,
+    wind_direction REAL,
+    rain_intensity REAL,
+    rain_accumulation REAL,
+    PRIMARY KEY (locality, time_ms)
+) WITHOUT ROWID;
+";
+
+/// Metrics left out of a reading are NULL, see [`MetricValue`] for NaN
+const COLUMNS: &str = "time_ms, device, temperature, humidity, wind_speed, wind_direction, rain_intensity, rain_accumulation";
+
+/// Readings in an embedded SQLite database, built with `--features sqlite`.\
+/// Times are stored as unix milliseconds.
+/// # Example usage
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use weather_union::history::{record, HistoryStore, Retention, SqliteHistory};
+/// use weather_union::{LocalityId, WeatherUnion};
+/// async fn example(){
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let mut history = SqliteHistory::open("readings.db").unwrap();
+///     history.prune(Retention::new().max_age(Duration::from_secs(30 * 86400)), SystemTime::now()).unwrap();
+///     record(&mut history, client.watch(LocalityId::in_city("Surat"), Duration::from_secs(600))).await.unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SqliteHistory {
+    connection: Connection,
+}
+
+/// Like [`super::millis`] but saturating, for query bounds.
+/// Times before the epoch sort before every stored reading and times too far ahead after every one.
+fn bound(at: SystemTime) -> i64 {
+    match at.duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => i64::try_from(since_epoch.as_millis()).unwrap_or(i64::MAX),
+        Err(_) => -1,
+    }
+}
+
+/// A metric column.\
+/// SQLite would store NaN as NULL and read it back as missing, so it is written as the text `NaN` instead.
+struct MetricValue(Option<f64>);
+
+impl ToSql for MetricValue {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match self.0 {
+            Some(value) if value.is_nan() => Ok(ToSqlOutput::from("NaN")),
+            _ => self.0.to_sql(),
+        }
+    }
+}
+
+impl FromSql for MetricValue {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<MetricValue> {
+        match value {
+            ValueRef::Text(b"NaN") => Ok(MetricValue(Some(f64::NAN))),
+            value => Option::<f64>::column_result(value).map(MetricValue),
+        }
+    }
+}
+
+fn reading(row: &Row) -> rusqlite::Result<(SystemTime, Reading)> {
+    let at = UNIX_EPOCH + Duration::from_millis(row.get::<_, i64>(0)?.max(0) as u64);
+    // Metric::ALL is in column order
+    let mut values = [None; 6];
+    for (index, value) in values.iter_mut().enumerate() {
+        *value = row.get::<_, MetricValue>(index + 2)?.0;
+    }
+    Ok((at, Reading::from_metrics(row.get(1)?, |metric| values[metric as usize])))
+}
+
+impl SqliteHistory {
+
+    /// Open or create a database file
+    pub fn open(path: impl AsRef<Path>) -> Result<SqliteHistory, HistoryError> {
+        SqliteHistory::from_connection(Connection::open(path).map_err(HistoryError::Sqlite)?)
+    }
+
+    /// A database that lives as long as the store, for tests
+    pub fn open_in_memory() -> Result<SqliteHistory, HistoryError> {
+        SqliteHistory::from_connection(Connection::open_in_memory().map_err(HistoryError::Sqlite)?)
+    }
+
+    /// Use an existing connection, creating the readings table if needed
+    pub fn from_connection(connection: Connection) -> Result<SqliteHistory, HistoryError> {
+        connection.execute_batch(SCHEMA).map_err(HistoryError::Sqlite)?;
+        Ok(SqliteHistory { connection })
+    }
+
+    fn before(&self, id: LocalityId, time_ms: i64) -> rusqlite::Result<Option<(SystemTime, Reading)>> {
+        self.connection
+            .prepare_cached(&format!(
+                "SELECT {} FROM readings WHERE locality = ?1 AND time_ms <= ?2 ORDER BY time_ms DESC LIMIT 1", COLUMNS
+            ))?
+            .query_row(params![id.0, time_ms], reading)
+            .optional()
+    }
+}
+
+impl HistoryStore for SqliteHistory {
+    fn append(&mut self, id: LocalityId, at: SystemTime, reading: Reading) -> Result<bool, HistoryError> {
+        let time_ms = millis(at)?;
+        let previous = self.before(id, time_ms).map_err(HistoryError::Sqlite)?;
+        // Also covers a stored reading at the same time, which would be replaced by itself
+        if previous.is_some_and(|(_, previous)| previous == reading) {
+            return Ok(false)
+        }
+        let value = |metric: Metric| MetricValue((!reading.missing.contains(metric)).then(|| reading.data.raw(metric)));
+        self.connection
+            .prepare_cached(&format!("INSERT OR REPLACE INTO readings (locality, {}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)", COLUMNS))
+            .and_then(|mut statement| statement.execute(params![
+                id.0, time_ms, reading.data.device, value(Metric::Temperature), value(Metric::Humidity), value(Metric::WindSpeed),
+                value(Metric::WindDirection), value(Metric::RainIntensity), value(Metric::RainAccumulation),
+            ]))
+            .map_err(HistoryError::Sqlite)?;
+        Ok(true)
+    }
+
+    fn range(&self, id: LocalityId, since: SystemTime, until: SystemTime) -> Result<Vec<(SystemTime, Reading)>, HistoryError> {
+        let (since, until) = (bound(since), bound(until));
+        let mut statement = self.connection
+            .prepare_cached(&format!(
+                "SELECT {} FROM readings WHERE locality = ?1 AND time_ms BETWEEN ?2 AND ?3 ORDER BY time_ms", COLUMNS
+            ))
+            .map_err(HistoryError::Sqlite)?;
+        let rows = statement.query_map(params![id.0, since, until], reading).map_err(HistoryError::Sqlite)?;
+        rows.collect::<rusqlite::Result<_>>().map_err(HistoryError::Sqlite)
+    }
+
+    fn latest(&self, id: LocalityId) -> Result<Option<(SystemTime, Reading)>, HistoryError> {
+        self.before(id, i64::MAX).map_err(HistoryError::Sqlite)
+    }
+
+    fn prune(&mut self, retention: Retention, now: SystemTime) -> Result<usize, HistoryError> {
+        let mut dropped = 0;
+        if let Some(cutoff) = retention.max_age.and_then(|age| now.checked_sub(age)) {
+            dropped += self.connection
+                .execute("DELETE FROM readings WHERE time_ms < ?1", params![bound(cutoff)])
+                .map_err(HistoryError::Sqlite)?;
+        }
+        if let Some(max) = retention.max_readings {
+            dropped += self.connection
+                .execute(
+                    "DELETE FROM readings WHERE (locality, time_ms) IN (
+                        SELECT locality, time_ms FROM (
+                            SELECT locality, time_ms, ROW_NUMBER() OVER (PARTITION BY locality ORDER BY time_ms DESC) AS newest
+                            FROM readings
+                        ) WHERE newest > ?1
+                    )",
+                    params![i64::try_from(max).unwrap_or(i64::MAX)],
+                )
+                .map_err(HistoryError::Sqlite)?;
+        }
+        Ok(dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::check_store;
+
+    #[test]
+    fn test_sqlite_history() {
+        check_store(&mut SqliteHistory::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn test_reopen_file() {
+        let path = std::env::temp_dir().join(format!("weather_union_history_{}.db", std::process::id()));
+        let at = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let data = Reading::from_metrics(2, |metric| (metric == Metric::RainIntensity).then_some(0.5));
+        SqliteHistory::open(&path).unwrap().append(LocalityId::ZWL008436, at, data).unwrap();
+        let reopened = SqliteHistory::open(&path).unwrap();
+        assert_eq!(reopened.latest(LocalityId::ZWL008436).unwrap(), Some((at, data)));
+        drop(reopened);
+        let _ = std::fs::remove_file(path);
+    }
+}
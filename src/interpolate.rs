@@ -0,0 +1,209 @@
+use crate::geo::haversine_km;
+use crate::{LocalityId, Metric, Reading};
+
+/// How station values are weighted when estimating a point
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    /// Inverse distance weighting, weights of `1 / distance^power`.
+    InverseDistance { power: f64 },
+    /// Ordinary kriging with an exponential variogram reaching its sill at `range_km`.\
+    /// `nugget` is the fraction of the variance (0 to 1) attributed to measurement noise.
+    Kriging { range_km: f64, nugget: f64 },
+}
+
+/// An interpolated value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    pub value: f64,
+    /// 1.0 at a station, falling towards 0.0 as the nearest station approaches the cutoff distance
+    /// and when only one or two stations contribute.
+    pub confidence: f64,
+    /// Number of stations the estimate was built from
+    pub stations: usize,
+    pub nearest_km: f64,
+}
+
+/// Estimates metrics at arbitrary points from readings of nearby localities
+/// # Example usage
+/// ```
+/// use weather_union::interpolate::{Interpolator, Method};
+/// use weather_union::{LocalityId, Metric, WeatherUnion};
+/// async fn example(){
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let mut readings = Vec::new();
+///     for id in LocalityId::in_city("Bengaluru") {
+///         if let Ok(data) = client.locality_reading(id).await {
+///             readings.push((id, data));
+///         }
+///     }
+///     let interpolator = Interpolator::new(&readings)
+///         .method(Method::InverseDistance { power: 2.0 })
+///         .max_distance_km(8.0);
+///     if let Some(estimate) = interpolator.estimate(Metric::Temperature, (12.936787, 77.556079)) {
+///         println!("{} (confidence {})", estimate.value, estimate.confidence);
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Interpolator {
+    stations: Vec<((f64, f64), Reading)>,
+    method: Method,
+    max_distance_km: f64,
+    neighbours: usize,
+}
+
+impl Interpolator {
+
+    /// Build from readings of localities with known coordinates, others are ignored.\
+    /// Defaults to inverse distance weighting with power 2, a 10 km cutoff and 8 neighbours.
+    pub fn new<'a>(readings: impl IntoIterator<Item = &'a (LocalityId, Reading)>) -> Interpolator {
+        let stations = readings.into_iter()
+            .filter_map(|(id, data)| Some((id.locality_lat_long()?, *data)))
+            .collect();
+        Interpolator {
+            stations,
+            method: Method::InverseDistance { power: 2.0 },
+            max_distance_km: 10.0,
+            neighbours: 8,
+        }
+    }
+
+    pub fn method(mut self, method: Method) -> Interpolator {
+        self.method = method;
+        self
+    }
+
+    /// Stations further than this from the point are not used.
+    pub fn max_distance_km(mut self, km: f64) -> Interpolator {
+        self.max_distance_km = km;
+        self
+    }
+
+    /// Use at most this many of the nearest stations.
+    pub fn neighbours(mut self, neighbours: usize) -> Interpolator {
+        self.neighbours = neighbours.max(1);
+        self
+    }
+
+    /// Estimate a metric at (lat, long), None if no station reporting it is within the cutoff.
+    pub fn estimate(&self, metric: Metric, point: (f64, f64)) -> Option<Estimate> {
+        let mut near: Vec<((f64, f64), f64, f64)> = self.stations.iter()
+            .filter_map(|(lat_long, data)| {
+                let value = data.metric(metric).filter(|v| v.is_finite())?;
+                let distance = haversine_km(point, *lat_long);
+                (distance <= self.max_distance_km).then_some((*lat_long, distance, value))
+            })
+            .collect();
+        near.sort_by(|a, b| a.1.total_cmp(&b.1));
+        near.truncate(self.neighbours);
+        let nearest_km = near.first()?.1;
+
+        let weights = if nearest_km < 1e-6 {
+            let mut weights = vec![0.0; near.len()];
+            weights[0] = 1.0;
+            weights
+        } else {
+            match self.method {
+                Method::InverseDistance { power } => inverse_distance_weights(&near, power),
+                Method::Kriging { range_km, nugget } => kriging_weights(&near, point, range_km, nugget)
+                    .unwrap_or_else(|| inverse_distance_weights(&near, 2.0)),
+            }
+        };
+        let value = if metric == Metric::WindDirection {
+            let (sin, cos) = near.iter().zip(&weights).fold((0.0, 0.0), |(sin, cos), (station, w)| {
+                (sin + w * station.2.to_radians().sin(), cos + w * station.2.to_radians().cos())
+            });
+            sin.atan2(cos).to_degrees().rem_euclid(360.0)
+        } else {
+            near.iter().zip(&weights).map(|(station, w)| w * station.2).sum()
+        };
+        let support = near.len().min(3) as f64 / 3.0;
+        let confidence = if nearest_km < 1e-6 { 1.0 } else {
+            ((1.0 - nearest_km / self.max_distance_km) * support).clamp(0.0, 1.0)
+        };
+        Some(Estimate { value, confidence, stations: near.len(), nearest_km })
+    }
+}
+
+fn inverse_distance_weights(near: &[((f64, f64), f64, f64)], power: f64) -> Vec<f64> {
+    let raw: Vec<f64> = near.iter().map(|station| 1.0 / station.1.powf(power)).collect();
+    let total: f64 = raw.iter().sum();
+    raw.into_iter().map(|w| w / total).collect()
+}
+
+/// Solves the ordinary kriging system, None if it is singular.
+fn kriging_weights(near: &[((f64, f64), f64, f64)], point: (f64, f64), range_km: f64, nugget: f64) -> Option<Vec<f64>> {
+    let variogram = |h: f64| if h <= 0.0 { 0.0 } else { nugget + (1.0 - nugget) * (1.0 - (-3.0 * h / range_km).exp()) };
+    let n = near.len();
+    // Augmented (n + 1) x (n + 2) matrix, the extra row and column are the Lagrange multiplier
+    let mut matrix = vec![vec![0.0; n + 2]; n + 1];
+    for i in 0..n {
+        for j in 0..n {
+            matrix[i][j] = variogram(haversine_km(near[i].0, near[j].0));
+        }
+        matrix[i][n] = 1.0;
+        matrix[n][i] = 1.0;
+        matrix[i][n + 1] = variogram(haversine_km(near[i].0, point));
+    }
+    matrix[n][n + 1] = 1.0;
+
+    for column in 0..=n {
+        let pivot = (column..=n).max_by(|a, b| matrix[*a][column].abs().total_cmp(&matrix[*b][column].abs()))?;
+        if matrix[pivot][column].abs() < 1e-12 {
+            return None
+        }
+        matrix.swap(column, pivot);
+        let pivot_row = matrix[column].clone();
+        for (index, row) in matrix.iter_mut().enumerate() {
+            if index != column {
+                let factor = row[column] / pivot_row[column];
+                for (value, pivot_value) in row.iter_mut().zip(&pivot_row).skip(column) {
+                    *value -= factor * pivot_value;
+                }
+            }
+        }
+    }
+    Some((0..n).map(|i| matrix[i][n + 1] / matrix[i][i]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aws, LocalityWeatherData};
+
+    fn readings() -> Vec<(LocalityId, Reading)> {
+        vec![
+            (LocalityId::ZWL005764, aws(30.0).into()), // Sarita Vihar
+            (LocalityId::ZWL005996, aws(32.0).into()), // New Friends Colony
+            (LocalityId::ZWL004800, aws(31.0).into()), // Kalkaji
+        ]
+    }
+
+    #[test]
+    fn test_estimate_at_station_is_exact() {
+        let interpolator = Interpolator::new(&readings());
+        let estimate = interpolator.estimate(Metric::Temperature, (28.531759, 77.293973)).unwrap();
+        assert_eq!(estimate.value, 30.0);
+        assert_eq!(estimate.confidence, 1.0);
+    }
+
+    #[test]
+    fn test_estimate_between_stations() {
+        let readings = readings();
+        for method in [Method::InverseDistance { power: 2.0 }, Method::Kriging { range_km: 10.0, nugget: 0.1 }] {
+            let interpolator = Interpolator::new(&readings).method(method);
+            let estimate = interpolator.estimate(Metric::Temperature, (28.545, 77.28)).unwrap();
+            assert!(estimate.value > 30.0 && estimate.value < 32.0, "{:?} {:?}", method, estimate);
+            assert_eq!(estimate.stations, 3);
+            assert!(estimate.confidence > 0.0 && estimate.confidence < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_cutoff_and_unreported_metrics() {
+        let interpolator = Interpolator::new(&readings()).max_distance_km(5.0);
+        assert!(interpolator.estimate(Metric::Temperature, (12.936787, 77.556079)).is_none());
+        let rgs = [(LocalityId::ZWL005764, LocalityWeatherData { device: 2, ..aws(30.0) }.into())];
+        assert!(Interpolator::new(&rgs).estimate(Metric::Temperature, (28.53, 77.29)).is_none());
+    }
+}
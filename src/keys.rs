@@ -0,0 +1,432 @@
+use std::fmt::{self, Formatter};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use zeroize::Zeroize;
+
+/// Longest key accepted, to catch a whole file or document passed by mistake
+const MAX_KEY_LENGTH: usize = 1024;
+
+/// A WeatherUnion api key.\
+/// The value is wiped from memory on drop and never shown by `Debug`, only [`ApiKey::expose`] reveals it.
+/// # Example usage
+/// ```
+/// use weather_union::WeatherUnion;
+/// use weather_union::keys::ApiKey;
+/// let key = ApiKey::from_env("WEATHER_UNION_API_KEY")
+///     .or_else(|_| ApiKey::from_file("target/api_key"));
+/// if let Ok(key) = key {
+///     println!("{:?}", key); // ApiKey(<redacted>)
+///     let client = WeatherUnion::from_api_key(key);
+/// }
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    key: String,
+}
+
+#[derive(Debug)]
+pub enum ApiKeyError {
+    Empty,
+    TooLong(usize),
+    /// Position of the first character that cannot be sent as is in a header: whitespace, control or non-ASCII
+    InvalidCharacter(usize),
+    Env(std::env::VarError),
+    Io(std::io::Error),
+}
+
+impl ApiKey {
+
+    /// Validate a key, surrounding whitespace such as the newline at the end of a key file is removed.
+    pub fn new(key: impl Into<String>) -> Result<ApiKey, ApiKeyError> {
+        let key = ApiKey::unchecked(key);
+        key.validate()?;
+        Ok(key)
+    }
+
+    /// Read a key from an environment variable
+    pub fn from_env(var: &str) -> Result<ApiKey, ApiKeyError> {
+        ApiKey::new(std::env::var(var).map_err(ApiKeyError::Env)?)
+    }
+
+    /// Read a key from a file holding only the key
+    pub fn from_file(path: impl AsRef<Path>) -> Result<ApiKey, ApiKeyError> {
+        ApiKey::new(std::fs::read_to_string(path).map_err(ApiKeyError::Io)?)
+    }
+
+    /// Trimmed but not validated, requests with an invalid key fail before they are sent.
+    pub(crate) fn unchecked(key: impl Into<String>) -> ApiKey {
+        let mut raw = key.into();
+        let key = raw.trim().to_string();
+        raw.zeroize();
+        ApiKey { key }
+    }
+
+    /// Check the key can be sent in a request header.\
+    /// WeatherUnion does not document a key format, so any 1 to 1024 visible ASCII characters are accepted.
+    pub fn validate(&self) -> Result<(), ApiKeyError> {
+        if self.key.is_empty() {
+            return Err(ApiKeyError::Empty)
+        }
+        if self.key.len() > MAX_KEY_LENGTH {
+            return Err(ApiKeyError::TooLong(self.key.len()))
+        }
+        match self.key.chars().position(|c| !(c.is_ascii_graphic())) {
+            Some(position) => Err(ApiKeyError::InvalidCharacter(position)),
+            None => Ok(()),
+        }
+    }
+
+    /// The key itself, for the request header
+    pub fn expose(&self) -> &str {
+        &self.key
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "ApiKey(<redacted>)")
+    }
+}
+
+impl Drop for ApiKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+/// A key that could not be loaded by [`KeyPool::from_env_or_files`]
+#[derive(Debug)]
+pub struct KeyLoadError {
+    /// The environment variable or file the key was read from
+    pub source: String,
+    pub error: ApiKeyError,
+}
+
+/// How a [`KeyPool`] picks the key for the next request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    /// Take turns, skipping keys that are cooling down
+    RoundRobin,
+    /// The available key with the fewest requests so far
+    LeastUsed,
+}
+
+/// Usage of one key of a [`KeyPool`], in the order the keys were given
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KeyStats {
+    pub requests: u64,
+    pub successes: u64,
+    /// Answered with 429, see [`crate::WeatherResponseError::ApiKeyLimitExhausted`]
+    pub rate_limited: u64,
+    /// Answered with 403, see [`crate::WeatherResponseError::CouldNotAuthenticate`]
+    pub rejected: u64,
+    /// Any other error
+    pub failures: u64,
+    /// Time left before the key is used again, None if it is available
+    pub cooldown: Option<Duration>,
+}
+
+/// The result of a request made with a key, reported back to the pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Success,
+    RateLimited,
+    Rejected,
+    Failure,
+}
+
+#[derive(Debug)]
+struct KeyState {
+    stats: KeyStats,
+    cooling_until: Option<Duration>,
+}
+
+/// Several api keys used by one [`crate::WeatherUnion`].\
+/// A key answered with 429 or 403 cools down and the request is retried with the next available key.
+/// If every key is cooling down when a request starts, the one available soonest is tried once anyway
+/// and its error returned, so a pool-wide cooldown costs one upstream request per call.
+/// # Example usage
+/// ```
+/// use std::time::Duration;
+/// use weather_union::WeatherUnion;
+/// use weather_union::keys::{KeyPool, Selection};
+/// let pool = KeyPool::new(["first_key", "second_key"])
+///     .selection(Selection::LeastUsed)
+///     .cooldown(Duration::from_secs(300)); // rest a key for 5 minutes after a 429
+/// let client = WeatherUnion::from_pool(pool);
+/// println!("{:?}", client.key_stats());
+/// ```
+#[derive(Debug)]
+pub struct KeyPool {
+    keys: Vec<ApiKey>,
+    selection: Selection,
+    cooldown: Duration,
+    auth_cooldown: Duration,
+    state: Mutex<PoolState>,
+}
+
+#[derive(Debug)]
+struct PoolState {
+    keys: Vec<KeyState>,
+    next: usize,
+}
+
+impl KeyPool {
+
+    /// Round-robin over `keys`, resting a key 60 seconds after a 429 and an hour after a 403.\
+    /// Keys are checked when used, see [`ApiKey::validate`], and an empty pool fails every request
+    /// with [`crate::WeatherResponseError::NoApiKeys`].
+    pub fn new<K: Into<String>>(keys: impl IntoIterator<Item = K>) -> KeyPool {
+        KeyPool::from_keys(keys.into_iter().map(ApiKey::unchecked))
+    }
+
+    /// Round-robin over keys that were already validated
+    pub fn from_keys(keys: impl IntoIterator<Item = ApiKey>) -> KeyPool {
+        let keys: Vec<ApiKey> = keys.into_iter().collect();
+        let state = keys.iter().map(|_| KeyState { stats: KeyStats::default(), cooling_until: None }).collect();
+        KeyPool {
+            keys,
+            selection: Selection::RoundRobin,
+            cooldown: Duration::from_secs(60),
+            auth_cooldown: Duration::from_secs(3600),
+            state: Mutex::new(PoolState { keys: state, next: 0 }),
+        }
+    }
+
+    /// One key per file in `paths`, or the key in the environment variable `var` when no paths are given,
+    /// the way the command line tools take their keys.
+    pub fn from_env_or_files(var: &str, paths: &[impl AsRef<Path>]) -> Result<KeyPool, KeyLoadError> {
+        if paths.is_empty() {
+            let key = ApiKey::from_env(var).map_err(|error| KeyLoadError { source: var.to_string(), error })?;
+            return Ok(KeyPool::from_keys([key]))
+        }
+        let keys = paths.iter()
+            .map(|path| ApiKey::from_file(path).map_err(|error| KeyLoadError { source: path.as_ref().display().to_string(), error }))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(KeyPool::from_keys(keys))
+    }
+
+    pub fn selection(mut self, selection: Selection) -> KeyPool {
+        self.selection = selection;
+        self
+    }
+
+    /// How long a key rests after a 429
+    pub fn cooldown(mut self, cooldown: Duration) -> KeyPool {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// How long a key rests after a 403
+    pub fn auth_cooldown(mut self, cooldown: Duration) -> KeyPool {
+        self.auth_cooldown = cooldown;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Usage of every key, in the order they were given
+    pub fn stats(&self) -> Vec<KeyStats> {
+        self.stats_at(now())
+    }
+
+    /// Pick a key not in `tried`, returning its index and value.\
+    /// A cooling key is only picked for the first attempt of a request, when no key is available.
+    pub(crate) fn acquire(&self, tried: &[usize]) -> Option<(usize, &ApiKey)> {
+        self.acquire_at(now(), tried)
+    }
+
+    /// Record how a request made with key `index` went.
+    pub(crate) fn report(&self, index: usize, outcome: Outcome) {
+        self.report_at(now(), index, outcome)
+    }
+
+    fn stats_at(&self, now: Duration) -> Vec<KeyStats> {
+        let state = self.lock();
+        state.keys.iter().map(|key| {
+            let mut stats = key.stats.clone();
+            stats.cooldown = key.cooling_until.filter(|until| *until > now).map(|until| until - now);
+            stats
+        }).collect()
+    }
+
+    fn acquire_at(&self, now: Duration, tried: &[usize]) -> Option<(usize, &ApiKey)> {
+        let mut state = self.lock();
+        let count = state.keys.len();
+        let candidates: Vec<usize> = (0..count).map(|i| (state.next + i) % count).filter(|i| !tried.contains(i)).collect();
+        let available: Vec<usize> = candidates.iter().copied()
+            .filter(|i| state.keys[*i].cooling_until.is_none_or(|until| until <= now))
+            .collect();
+        let index = if available.is_empty() {
+            // Every key is resting, probe the one that recovers first rather than not sending at all,
+            // but only once per request as the others are expected to fail too
+            if !tried.is_empty() {
+                return None
+            }
+            candidates.into_iter().min_by_key(|i| state.keys[*i].cooling_until)?
+        } else {
+            match self.selection {
+                Selection::RoundRobin => available[0],
+                Selection::LeastUsed => available.into_iter().min_by_key(|i| (state.keys[*i].stats.requests, *i))?,
+            }
+        };
+        state.next = (index + 1) % count;
+        state.keys[index].stats.requests += 1;
+        Some((index, &self.keys[index]))
+    }
+
+    fn report_at(&self, now: Duration, index: usize, outcome: Outcome) {
+        let mut state = self.lock();
+        let Some(key) = state.keys.get_mut(index) else { return };
+        match outcome {
+            Outcome::Success => {
+                key.stats.successes += 1;
+                key.cooling_until = None;
+            }
+            Outcome::RateLimited => {
+                key.stats.rate_limited += 1;
+                key.cooling_until = Some(now + self.cooldown);
+            }
+            Outcome::Rejected => {
+                key.stats.rejected += 1;
+                key.cooling_until = Some(now + self.auth_cooldown);
+            }
+            Outcome::Failure => key.stats.failures += 1,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Monotonic time since the first call
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now() -> Duration {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START.get_or_init(std::time::Instant::now).elapsed()
+}
+
+/// `Instant` panics in the browser, use the JS clock instead
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now() -> Duration {
+    Duration::from_secs_f64(js_sys::Date::now() / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[cfg(feature = "mock")]
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    #[test]
+    fn test_api_key() {
+        let key = ApiKey::new("abc123_XYZ-9\n").unwrap();
+        assert_eq!(key.expose(), "abc123_XYZ-9");
+        assert_eq!(format!("{:?}", key), "ApiKey(<redacted>)");
+        assert!(matches!(ApiKey::new(" \r\n"), Err(ApiKeyError::Empty)));
+        assert!(matches!(ApiKey::new("abc def"), Err(ApiKeyError::InvalidCharacter(3))));
+        assert!(matches!(ApiKey::new("é"), Err(ApiKeyError::InvalidCharacter(0))));
+        assert!(ApiKey::new("k3y.with+symbols/=").is_ok());
+        assert!(matches!(ApiKey::new("k".repeat(1025)), Err(ApiKeyError::TooLong(1025))));
+        assert!(matches!(ApiKey::from_env("WEATHER_UNION_TEST_UNSET_VARIABLE"), Err(ApiKeyError::Env(_))));
+
+        let path = std::env::temp_dir().join(format!("weather_union_key_{}", std::process::id()));
+        std::fs::write(&path, "from_file_key\r\n").unwrap();
+        assert_eq!(ApiKey::from_file(&path).unwrap().expose(), "from_file_key");
+        let pool = KeyPool::from_env_or_files("WEATHER_UNION_TEST_UNSET_VARIABLE", &[&path, &path]).unwrap();
+        assert_eq!(pool.len(), 2);
+        std::fs::remove_file(&path).unwrap();
+        let missing = KeyPool::from_env_or_files("WEATHER_UNION_TEST_UNSET_VARIABLE", &[&path]).unwrap_err();
+        assert!(matches!(missing.error, ApiKeyError::Io(_)) && missing.source.contains("weather_union_key_"));
+        let unset = KeyPool::from_env_or_files("WEATHER_UNION_TEST_UNSET_VARIABLE", &[] as &[&str]).unwrap_err();
+        assert_eq!(unset.source, "WEATHER_UNION_TEST_UNSET_VARIABLE");
+        // Pools keep invalid keys, requests made with them fail before being sent
+        assert!(KeyPool::new(["bad key"]).acquire_at(SECOND, &[]).unwrap().1.validate().is_err());
+    }
+
+    #[test]
+    fn test_round_robin_skips_cooling_keys() {
+        let pool = KeyPool::new(["a", "b", "c"]).cooldown(SECOND * 10);
+        let picks: Vec<&str> = (0..4).map(|_| pool.acquire_at(SECOND, &[]).unwrap().1.expose()).collect();
+        assert_eq!(picks, ["a", "b", "c", "a"]);
+        pool.report_at(SECOND, 1, Outcome::RateLimited);
+        let picks: Vec<&str> = (0..3).map(|_| pool.acquire_at(SECOND * 2, &[]).unwrap().1.expose()).collect();
+        assert_eq!(picks, ["c", "a", "c"]);
+        // Rested long enough
+        assert_eq!(pool.acquire_at(SECOND * 11, &[]).unwrap().1.expose(), "a");
+        assert_eq!(pool.acquire_at(SECOND * 11, &[]).unwrap().1.expose(), "b");
+        assert_eq!(pool.stats_at(SECOND * 2)[1].cooldown, Some(SECOND * 9));
+    }
+
+    #[test]
+    fn test_least_used_and_exhaustion() {
+        let pool = KeyPool::new(["a", "b"]).selection(Selection::LeastUsed).auth_cooldown(SECOND * 100);
+        pool.acquire_at(SECOND, &[]);
+        pool.acquire_at(SECOND, &[]);
+        pool.acquire_at(SECOND, &[]);
+        let stats = pool.stats_at(SECOND);
+        assert_eq!((stats[0].requests, stats[1].requests), (2, 1));
+        assert_eq!(pool.acquire_at(SECOND, &[]).unwrap().1.expose(), "b");
+
+        pool.report_at(SECOND, 0, Outcome::Rejected);
+        pool.report_at(SECOND * 2, 1, Outcome::Rejected);
+        // Both resting, the one rejected first comes back first
+        assert_eq!(pool.acquire_at(SECOND * 3, &[]).unwrap().1.expose(), "a");
+        // and no other resting key is tried for the same request
+        assert!(pool.acquire_at(SECOND * 3, &[0]).is_none());
+        // An available key is still tried after a resting one
+        assert_eq!(pool.acquire_at(SECOND * 200, &[0]).unwrap().1.expose(), "b");
+        assert!(pool.acquire_at(SECOND * 200, &[0, 1]).is_none());
+        assert_eq!(pool.stats_at(SECOND * 3)[0].rejected, 1);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_key_pool_failover_on_quotas() {
+        use crate::mock::{MockConfig, MockServer};
+        use crate::{LocalityId, WeatherResponseError};
+
+        aw!(async {
+            let config = MockConfig::new().api_key("first").api_key("second").api_key("third")
+                .quota("first", 2).quota("second", 1);
+            let server = MockServer::start(config).await.unwrap();
+            let client = server.pool_client(KeyPool::new(["revoked", "first", "second", "third"]));
+            for _ in 0..6 {
+                assert!(client.locality(LocalityId::ZWL005764).await.is_ok());
+            }
+            let stats = client.key_stats();
+            assert_eq!(stats[0].rejected, 1);
+            assert!(stats[0].cooldown.is_some());
+            // Round-robin spreads the load until each quota runs out, then fails over
+            assert_eq!((stats[1].successes, stats[1].rate_limited), (2, 1));
+            assert_eq!((stats[2].successes, stats[2].rate_limited), (1, 1));
+            assert_eq!((stats[3].successes, stats[3].rate_limited), (3, 0));
+            assert_eq!((server.usage("first"), server.usage("second"), server.usage("third")), (3, 2, 3));
+
+            // Every key resting or over quota, the last error is returned
+            let client = server.pool_client(KeyPool::new(["first", "second"]));
+            assert!(matches!(client.locality(LocalityId::ZWL005764).await, Err(WeatherResponseError::ApiKeyLimitExhausted)));
+            assert_eq!(client.key_stats().iter().map(|s| s.rate_limited).sum::<u64>(), 2);
+            // Both keys cooling down, a single key is tried
+            let used = server.usage("first") + server.usage("second");
+            assert!(matches!(client.locality(LocalityId::ZWL005764).await, Err(WeatherResponseError::ApiKeyLimitExhausted)));
+            assert_eq!(server.usage("first") + server.usage("second"), used + 1);
+        });
+    }
+}
@@ -0,0 +1,174 @@
+use core::fmt;
+use std::fmt::Formatter;
+
+use crate::{LocalityWeatherData, Reading};
+
+/// A single measured quantity of [`LocalityWeatherData`]
+/// # Example usage
+/// ```
+/// use weather_union::{LocalityId, Metric, WeatherUnion};
+/// async fn example(){
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let data = client.locality(LocalityId::ZWL001036).await.unwrap();
+///     // None when the device does not measure temperature (e.g. a rain gauge)
+///     let temperature = data.metric(Metric::Temperature);
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Temperature, Humidity, WindSpeed, WindDirection, RainIntensity, RainAccumulation
+}
+
+impl Metric {
+
+    /// Every metric, in API field order.
+    pub const ALL: [Metric; 6] = [
+        Metric::Temperature, Metric::Humidity, Metric::WindSpeed,
+        Metric::WindDirection, Metric::RainIntensity, Metric::RainAccumulation,
+    ];
+
+    /// The field name used by the WeatherUnion API
+    /// # Example
+    /// ```
+    ///  use weather_union::Metric;
+    ///  assert_eq!(Metric::RainIntensity.name(), "rain_intensity");
+    /// ```
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Temperature => "temperature",
+            Metric::Humidity => "humidity",
+            Metric::WindSpeed => "wind_speed",
+            Metric::WindDirection => "wind_direction",
+            Metric::RainIntensity => "rain_intensity",
+            Metric::RainAccumulation => "rain_accumulation",
+        }
+    }
+
+    /// Returns true if a device of the given type measures this metric.\
+    /// Automated weather systems (1) report everything, rain gauge systems (2) only rain.
+    pub fn is_reported_by(&self, device: u8) -> bool {
+        match device {
+            1 => true,
+            2 => matches!(self, Metric::RainIntensity | Metric::RainAccumulation),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A set of [`Metric`]s
+/// # Example
+/// ```
+///  use weather_union::{Metric, MetricSet};
+///  let rain: MetricSet = [Metric::RainIntensity, Metric::RainAccumulation].into_iter().collect();
+///  assert!(rain.contains(Metric::RainIntensity) && !rain.contains(Metric::Humidity));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MetricSet(u8);
+
+impl MetricSet {
+
+    pub const EMPTY: MetricSet = MetricSet(0);
+
+    fn bit(metric: Metric) -> u8 {
+        1 << metric as u8
+    }
+
+    pub fn contains(&self, metric: Metric) -> bool {
+        self.0 & MetricSet::bit(metric) != 0
+    }
+
+    pub fn insert(&mut self, metric: Metric) {
+        self.0 |= MetricSet::bit(metric);
+    }
+
+    pub fn remove(&mut self, metric: Metric) {
+        self.0 &= !MetricSet::bit(metric);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// The metrics in the set, in [`Metric::ALL`] order
+    pub fn iter(&self) -> impl Iterator<Item = Metric> + '_ {
+        Metric::ALL.into_iter().filter(|metric| self.contains(*metric))
+    }
+}
+
+impl FromIterator<Metric> for MetricSet {
+    fn from_iter<I: IntoIterator<Item = Metric>>(metrics: I) -> MetricSet {
+        let mut set = MetricSet::EMPTY;
+        for metric in metrics {
+            set.insert(metric);
+        }
+        set
+    }
+}
+
+impl LocalityWeatherData {
+
+    /// Value of a metric, or None if the reporting device does not measure it.\
+    /// A metric left out of the reading also reads 0 here, see [`Reading::metric`] to tell it apart.
+    pub fn metric(&self, metric: Metric) -> Option<f64> {
+        metric.is_reported_by(self.device).then(|| self.raw(metric))
+    }
+
+    /// The value of a metric whether or not the device measures it
+    pub(crate) fn raw(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::Temperature => self.temperature,
+            Metric::Humidity => self.humidity,
+            Metric::WindSpeed => self.wind_speed,
+            Metric::WindDirection => self.wind_direction,
+            Metric::RainIntensity => self.rain_intensity,
+            Metric::RainAccumulation => self.rain_accumulation,
+        }
+    }
+
+    pub(crate) fn set_raw(&mut self, metric: Metric, value: f64) {
+        let field = match metric {
+            Metric::Temperature => &mut self.temperature,
+            Metric::Humidity => &mut self.humidity,
+            Metric::WindSpeed => &mut self.wind_speed,
+            Metric::WindDirection => &mut self.wind_direction,
+            Metric::RainIntensity => &mut self.rain_intensity,
+            Metric::RainAccumulation => &mut self.rain_accumulation,
+        };
+        *field = value;
+    }
+}
+
+impl Reading {
+
+    /// Value of a metric, or None if the reporting device does not measure it or left it out of the reading.
+    pub fn metric(&self, metric: Metric) -> Option<f64> {
+        self.data.metric(metric).filter(|_| !self.missing.contains(metric))
+    }
+
+    /// Reading of a device, None values read as 0 and are marked missing if the device measures them
+    pub(crate) fn from_metrics(device: u8, value: impl Fn(Metric) -> Option<f64>) -> Reading {
+        let mut reading = Reading { data: LocalityWeatherData { device, ..Default::default() }, missing: MetricSet::EMPTY };
+        for metric in Metric::ALL {
+            match value(metric) {
+                Some(value) => reading.data.set_raw(metric, value),
+                None if metric.is_reported_by(device) => reading.missing.insert(metric),
+                None => {}
+            }
+        }
+        reading
+    }
+}
+
+/// A reading with nothing missing
+impl From<LocalityWeatherData> for Reading {
+    fn from(data: LocalityWeatherData) -> Reading {
+        Reading { data, missing: MetricSet::EMPTY }
+    }
+}
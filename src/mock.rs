@@ -0,0 +1,351 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::get;
+use axum::Router;
+use serde_json::json;
+use tokio::sync::oneshot;
+
+use crate::keys::KeyPool;
+use crate::spatial::SpatialIndex;
+use crate::watch::XorShift;
+use crate::{BodyValues, LocalityId, LocalityWeatherData, Reading, WeatherUnion};
+
+/// Localities further than this from a lat_long query are not supported
+const MAX_LAT_LONG_DISTANCE_KM: f64 = 5.0;
+
+/// Behaviour of a [`MockServer`]
+/// # Example usage
+/// ```
+/// use std::time::Duration;
+/// use weather_union::mock::MockConfig;
+/// let config = MockConfig::new()
+///     .api_key("test_key") // reject every other key with 403
+///     .quota("test_key", 100) // answer 429 after 100 requests with this key
+///     .latency(Duration::from_millis(50))
+///     .failure_rate(0.1) // one in ten requests fails with 500
+///     .seed(7);
+/// ```
+#[derive(Clone, Debug)]
+pub struct MockConfig {
+    api_keys: Option<HashSet<String>>,
+    quotas: HashMap<String, u64>,
+    latency: Duration,
+    failure_rate: f64,
+    seed: u64,
+}
+
+impl Default for MockConfig {
+    fn default() -> MockConfig {
+        MockConfig { api_keys: None, quotas: HashMap::new(), latency: Duration::ZERO, failure_rate: 0.0, seed: 0x5eed }
+    }
+}
+
+impl MockConfig {
+
+    /// Accept any api key, no latency or random failures
+    pub fn new() -> MockConfig {
+        MockConfig::default()
+    }
+
+    /// Only accept the listed keys, may be called several times.
+    pub fn api_key(mut self, key: impl Into<String>) -> MockConfig {
+        self.api_keys.get_or_insert_with(HashSet::new).insert(key.into());
+        self
+    }
+
+    /// Answer 429 once `key` has made `requests` authenticated requests, like an exhausted daily limit.
+    pub fn quota(mut self, key: impl Into<String>, requests: u64) -> MockConfig {
+        self.quotas.insert(key.into(), requests);
+        self
+    }
+
+    /// Delay every response
+    pub fn latency(mut self, latency: Duration) -> MockConfig {
+        self.latency = latency;
+        self
+    }
+
+    /// Fraction of requests (0 to 1) answered with 500
+    pub fn failure_rate(mut self, rate: f64) -> MockConfig {
+        self.failure_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Seed of the generated readings and failures, the same seed gives the same sequence.
+    pub fn seed(mut self, seed: u64) -> MockConfig {
+        self.seed = seed;
+        self
+    }
+}
+
+/// A scripted answer to a request
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockResponse {
+    /// 200 with the reading
+    Reading(Reading),
+    /// 200 with a non empty message, see [`crate::WeatherResponseError::TemporarilyUnavailable`]
+    Unavailable(String),
+    /// Any status with an error body
+    Status(u16),
+    /// 200 with a body that is not valid JSON
+    Malformed,
+}
+
+struct MockState {
+    config: MockConfig,
+    rng: XorShift,
+    scripted: HashMap<LocalityId, VecDeque<MockResponse>>,
+    failures: VecDeque<MockResponse>,
+    requests: u64,
+    usage: HashMap<String, u64>,
+}
+
+/// Embedded HTTP server speaking the WeatherUnion API, for tests and demos.\
+/// Readings are generated unless scripted, the server stops when dropped.
+/// # Example usage
+/// ```
+/// use weather_union::mock::{MockConfig, MockResponse, MockServer};
+/// use weather_union::LocalityId;
+/// async fn example(){
+///     let server = MockServer::start(MockConfig::new().api_key("test_key")).await.unwrap();
+///     server.fail_next(MockResponse::Status(429));
+///     let client = server.client("test_key");
+///     assert!(client.locality(LocalityId::ZWL005764).await.is_err()); // the injected 429
+///     assert!(client.locality(LocalityId::ZWL005764).await.is_ok());
+/// }
+/// ```
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockServer {
+
+    /// Bind to a free local port and serve in the background of the current tokio runtime.
+    pub async fn start(config: MockConfig) -> std::io::Result<MockServer> {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState {
+            rng: XorShift(config.seed | 1),
+            config,
+            scripted: HashMap::new(),
+            failures: VecDeque::new(),
+            requests: 0,
+            usage: HashMap::new(),
+        }));
+        let router = Router::new()
+            .route("/gw/weather/external/v0/get_weather_data", get(lat_long))
+            .route("/gw/weather/external/v0/get_locality_weather_data", get(locality))
+            .with_state(state.clone());
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, router).with_graceful_shutdown(async { let _ = stopped.await; }).await;
+        });
+        Ok(MockServer { addr, state, shutdown: Some(shutdown) })
+    }
+
+    /// Base url of the server, see [`WeatherUnion::with_base_url`]
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A client sending requests to this server
+    pub fn client(&self, api_key: &str) -> WeatherUnion {
+        WeatherUnion::from_key(api_key.to_string()).with_base_url(self.url())
+    }
+
+    /// A client sending requests to this server with several keys
+    pub fn pool_client(&self, keys: KeyPool) -> WeatherUnion {
+        WeatherUnion::from_pool(keys).with_base_url(self.url())
+    }
+
+    /// Queue a response for the next request of a locality, by id or by lat_long.
+    pub fn script(&self, id: LocalityId, response: MockResponse) {
+        self.lock().scripted.entry(id).or_default().push_back(response);
+    }
+
+    /// Queue a response for the next authenticated request, whatever it asks for.
+    pub fn fail_next(&self, response: MockResponse) {
+        self.lock().failures.push_back(response);
+    }
+
+    /// Number of requests received so far
+    pub fn requests(&self) -> u64 {
+        self.lock().requests
+    }
+
+    /// Number of authenticated requests made with `key`, including those over its quota
+    pub fn usage(&self, key: &str) -> u64 {
+        self.lock().usage.get(key).copied().unwrap_or(0)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+type Reply = (StatusCode, String);
+
+fn error(status: StatusCode, message: &str) -> Reply {
+    (status, json!({ "status": status.as_u16().to_string(), "message": message }).to_string())
+}
+
+fn reply(response: MockResponse) -> Reply {
+    match response {
+        MockResponse::Reading(reading) => (StatusCode::OK, serde_json::to_string(&BodyValues::from(&reading)).unwrap_or_default()),
+        MockResponse::Unavailable(message) => {
+            let body = BodyValues { message, locality_weather_data: HashMap::new(), device_type: 0 };
+            (StatusCode::OK, serde_json::to_string(&body).unwrap_or_default())
+        }
+        MockResponse::Status(status) => {
+            let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            error(status, status.canonical_reason().unwrap_or(""))
+        }
+        MockResponse::Malformed => (StatusCode::OK, "{\"message\": ".to_string()),
+    }
+}
+
+/// A plausible reading, each locality keeps its own climate and drifts a little between requests
+fn generate(id: LocalityId, rng: &mut XorShift) -> LocalityWeatherData {
+    let base = id.0.bytes().fold(0u64, |hash, b| hash.wrapping_mul(31).wrapping_add(b as u64));
+    let climate = XorShift(base | 1).next_f64();
+    let raining = rng.next_f64() < 0.3;
+    let rain_intensity = if raining { rng.next_f64() * 15.0 } else { 0.0 };
+    LocalityWeatherData {
+        device: id.device_type().unwrap_or(1),
+        temperature: 18.0 + climate * 16.0 + rng.next_f64() * 4.0,
+        humidity: 35.0 + climate * 40.0 + rng.next_f64() * 20.0,
+        wind_speed: rng.next_f64() * 8.0,
+        wind_direction: rng.next_f64() * 360.0,
+        rain_intensity,
+        rain_accumulation: if raining { rain_intensity * (1.0 + rng.next_f64() * 3.0) } else { 0.0 },
+    }
+}
+
+async fn respond(state: Arc<Mutex<MockState>>, headers: HeaderMap, id: Option<LocalityId>) -> Reply {
+    let latency = state.lock().unwrap_or_else(|e| e.into_inner()).config.latency;
+    if !latency.is_zero() {
+        tokio::time::sleep(latency).await;
+    }
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+    state.requests += 1;
+    let key = headers.get("x-zomato-api-key").and_then(|k| k.to_str().ok()).unwrap_or("");
+    let authenticated = !key.is_empty() && state.config.api_keys.as_ref().is_none_or(|keys| keys.contains(key));
+    if !authenticated {
+        return error(StatusCode::FORBIDDEN, "Invalid API key")
+    }
+    let used = {
+        let used = state.usage.entry(key.to_string()).or_insert(0);
+        *used += 1;
+        *used
+    };
+    if state.config.quotas.get(key).is_some_and(|quota| used > *quota) {
+        return error(StatusCode::TOO_MANY_REQUESTS, "API key limit exhausted")
+    }
+    if let Some(failure) = state.failures.pop_front() {
+        return reply(failure)
+    }
+    if state.config.failure_rate > 0.0 && state.rng.next_f64() < state.config.failure_rate {
+        return error(StatusCode::INTERNAL_SERVER_ERROR, "Error retrieving data")
+    }
+    let Some(id) = id else {
+        return error(StatusCode::BAD_REQUEST, "Not supported")
+    };
+    if let Some(response) = state.scripted.get_mut(&id).and_then(|queue| queue.pop_front()) {
+        return reply(response)
+    }
+    let data = generate(id, &mut state.rng);
+    reply(MockResponse::Reading(data.into()))
+}
+
+async fn locality(State(state): State<Arc<Mutex<MockState>>>, headers: HeaderMap,
+                  Query(query): Query<HashMap<String, String>>) -> Reply {
+    let id = query.get("locality_id").and_then(|id| LocalityId::from_str(id).ok());
+    respond(state, headers, id).await
+}
+
+async fn lat_long(State(state): State<Arc<Mutex<MockState>>>, headers: HeaderMap,
+                  Query(query): Query<HashMap<String, String>>) -> Reply {
+    let lat = query.get("latitude").and_then(|v| v.parse::<f64>().ok());
+    let long = query.get("longitude").and_then(|v| v.parse::<f64>().ok());
+    let id = lat.zip(long)
+        .and_then(|point| SpatialIndex::builtin().nearest(point, 1).into_iter().next())
+        .filter(|(_, km)| *km <= MAX_LAT_LONG_DISTANCE_KM)
+        .map(|(id, _)| id);
+    respond(state, headers, id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WeatherResponseError;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    #[test]
+    fn test_generated_readings() {
+        aw!(async {
+            let server = MockServer::start(MockConfig::new()).await.unwrap();
+            let client = server.client("any_key");
+            let aws = client.locality(LocalityId::ZWL005764).await.unwrap();
+            assert_eq!(aws.device, 1);
+            assert!(aws.temperature > 15.0 && aws.temperature < 40.0);
+            let rgs = client.locality_id("ZWL008436").await.unwrap();
+            assert_eq!(rgs.device, 2);
+            assert_eq!(rgs.temperature, 0.0);
+            assert!(client.lat_long(28.531759, 77.293973).await.is_ok());
+            assert!(matches!(client.lat_long(51.5, -0.12).await, Err(WeatherResponseError::NotSupported)));
+            assert!(matches!(client.locality_id("ZWL000000").await, Err(WeatherResponseError::NotSupported)));
+            assert_eq!(server.requests(), 5);
+        });
+    }
+
+    #[test]
+    fn test_scripted_responses_and_failures() {
+        aw!(async {
+            let server = MockServer::start(MockConfig::new().api_key("good")).await.unwrap();
+            assert!(matches!(server.client("bad").locality(LocalityId::ZWL005764).await,
+                Err(WeatherResponseError::CouldNotAuthenticate)));
+            let client = server.client("good");
+            let reading = LocalityWeatherData { humidity: 40.0, wind_speed: 1.0, wind_direction: 180.0, ..crate::aws(21.5) };
+            server.script(LocalityId::ZWL005764, MockResponse::Reading(reading.into()));
+            server.script(LocalityId::ZWL005764, MockResponse::Unavailable("maintenance".to_string()));
+            server.fail_next(MockResponse::Status(429));
+            server.fail_next(MockResponse::Malformed);
+            assert!(matches!(client.locality(LocalityId::ZWL005764).await, Err(WeatherResponseError::ApiKeyLimitExhausted)));
+            assert!(matches!(client.locality(LocalityId::ZWL005764).await, Err(WeatherResponseError::InvalidResponse)));
+            assert_eq!(client.locality(LocalityId::ZWL005764).await.unwrap(), reading);
+            assert!(matches!(client.locality(LocalityId::ZWL005764).await, Err(WeatherResponseError::TemporarilyUnavailable(_))));
+        });
+    }
+
+    #[test]
+    fn test_failure_rate_and_latency() {
+        aw!(async {
+            let config = MockConfig::new().failure_rate(1.0).latency(Duration::from_millis(20));
+            let server = MockServer::start(config).await.unwrap();
+            let started = std::time::Instant::now();
+            let result = server.client("key").locality(LocalityId::ZWL005764).await;
+            assert!(matches!(result, Err(WeatherResponseError::ErrorRetrievingData)));
+            assert!(started.elapsed() >= Duration::from_millis(20));
+        });
+    }
+}
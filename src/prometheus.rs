@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::keys::KeyStats;
+use crate::{LocalityId, Metric, Reading, WeatherResponseError};
+
+/// Content type of [`Exporter::render`]
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Prometheus name and help text of a metric's gauge
+pub fn gauge(metric: Metric) -> (&'static str, &'static str) {
+    match metric {
+        Metric::Temperature => ("weatherunion_temperature_celsius", "Air temperature"),
+        Metric::Humidity => ("weatherunion_humidity_percent", "Relative humidity"),
+        Metric::WindSpeed => ("weatherunion_wind_speed_meters_per_second", "Wind speed"),
+        Metric::WindDirection => ("weatherunion_wind_direction_degrees", "Direction the wind blows from, clockwise from north"),
+        Metric::RainIntensity => ("weatherunion_rain_intensity_millimeters_per_minute", "Rain intensity"),
+        Metric::RainAccumulation => ("weatherunion_rain_accumulation_millimeters", "Rain accumulated since the start of the day"),
+    }
+}
+
+/// Latest readings and client health, rendered in the Prometheus text format
+/// # Example usage
+/// ```
+/// use std::time::SystemTime;
+/// use weather_union::prometheus::Exporter;
+/// use weather_union::{LocalityId, WeatherUnion};
+/// async fn example(){
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let mut exporter = Exporter::new();
+///     let id = LocalityId::ZWL005764;
+///     exporter.observe(id, &client.locality_reading(id).await, SystemTime::now());
+///     exporter.set_key_stats(&client.key_stats());
+///     println!("{}", exporter.render());
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Exporter {
+    readings: BTreeMap<LocalityId, (Reading, SystemTime)>,
+    requests: BTreeMap<LocalityId, u64>,
+    errors: BTreeMap<&'static str, u64>,
+    quota_hits: u64,
+}
+
+impl Exporter {
+
+    pub fn new() -> Exporter {
+        Exporter::default()
+    }
+
+    /// Count a request and keep its reading, a failed request keeps the previous reading.
+    pub fn observe(&mut self, id: LocalityId, result: &Result<Reading, WeatherResponseError>, at: SystemTime) {
+        *self.requests.entry(id).or_insert(0) += 1;
+        match result {
+            Ok(data) => {
+                self.readings.insert(id, (*data, at));
+            }
+            Err(error) => *self.errors.entry(error.variant()).or_insert(0) += 1,
+        }
+    }
+
+    /// Take the number of 429 answers from the client's keys, including those hidden by failover.
+    pub fn set_key_stats(&mut self, stats: &[KeyStats]) {
+        self.quota_hits = stats.iter().map(|key| key.rate_limited).sum();
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for metric in Metric::ALL {
+            let (name, help) = gauge(metric);
+            let samples: Vec<(LocalityId, f64)> = self.readings.iter()
+                .filter_map(|(id, (data, _))| Some((*id, data.metric(metric)?)))
+                .collect();
+            if samples.is_empty() {
+                continue
+            }
+            header(&mut out, name, help, "gauge");
+            for (id, value) in samples {
+                let _ = writeln!(out, "{}{} {}", name, labels(id), value);
+            }
+        }
+        if !self.readings.is_empty() {
+            header(&mut out, "weatherunion_reading_timestamp_seconds", "When the latest reading was received", "gauge");
+            for (id, (_, at)) in &self.readings {
+                let seconds = at.duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+                let _ = writeln!(out, "weatherunion_reading_timestamp_seconds{} {}", labels(*id), seconds);
+            }
+        }
+        header(&mut out, "weatherunion_requests_total", "Requests made per locality", "counter");
+        for (id, count) in &self.requests {
+            let _ = writeln!(out, "weatherunion_requests_total{{locality=\"{}\"}} {}", id.0, count);
+        }
+        header(&mut out, "weatherunion_errors_total", "Failed requests by WeatherResponseError variant", "counter");
+        for (variant, count) in &self.errors {
+            let _ = writeln!(out, "weatherunion_errors_total{{variant=\"{}\"}} {}", variant, count);
+        }
+        header(&mut out, "weatherunion_quota_hits_total", "Requests answered with 429 across all api keys", "counter");
+        let _ = writeln!(out, "weatherunion_quota_hits_total {}", self.quota_hits);
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn labels(id: LocalityId) -> String {
+    format!(
+        "{{locality=\"{}\",name=\"{}\",city=\"{}\"}}",
+        id.0, escape(id.locality_name().unwrap_or_default()), escape(id.city().unwrap_or_default())
+    )
+}
+
+/// Escape a label value, backslash, double quote and newline are special
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::{aws, rgs};
+
+    #[test]
+    fn test_render() {
+        let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut exporter = Exporter::new();
+        exporter.observe(LocalityId::ZWL005764, &Ok(aws(24.5).into()), at);
+        exporter.observe(LocalityId::ZWL008436, &Ok(rgs(0.25, 2.0).into()), at);
+        exporter.observe(LocalityId::ZWL008436, &Err(WeatherResponseError::ApiKeyLimitExhausted), at);
+        exporter.set_key_stats(&[KeyStats { rate_limited: 2, ..KeyStats::default() }, KeyStats { rate_limited: 1, ..KeyStats::default() }]);
+        let text = exporter.render();
+
+        assert!(text.contains("# TYPE weatherunion_temperature_celsius gauge\n"));
+        assert!(text.contains(
+            "weatherunion_temperature_celsius{locality=\"ZWL005764\",name=\"Delhi NCR Sarita Vihar\",city=\"Delhi NCR\"} 24.5\n"
+        ));
+        // The rain gauge only appears under rain metrics
+        assert_eq!(text.matches("locality=\"ZWL008436\",name").count(), 3);
+        assert!(text.contains("weatherunion_rain_intensity_millimeters_per_minute{locality=\"ZWL008436\""));
+        assert!(text.contains("weatherunion_reading_timestamp_seconds{locality=\"ZWL005764\""));
+        assert!(text.contains("} 1700000000\n"));
+        assert!(text.contains("weatherunion_requests_total{locality=\"ZWL008436\"} 2\n"));
+        assert!(text.contains("weatherunion_errors_total{variant=\"ApiKeyLimitExhausted\"} 1\n"));
+        assert!(text.contains("weatherunion_quota_hits_total 3\n"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a \"b\"\\c\nd"), "a \\\"b\\\"\\\\c\\nd");
+    }
+}
@@ -0,0 +1,561 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::{Query, State};
+use axum::http::header::RETRY_AFTER;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::routing::get;
+use axum::Router;
+use futures_util::future::{BoxFuture, FutureExt};
+use serde::Deserialize;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+use tokio::time::Instant;
+
+use crate::{BodyValues, Reading, WeatherResponseError, WeatherUnion};
+
+/// Requests a consumer may make per window
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quota {
+    pub requests: u64,
+    #[serde(rename = "window_secs", with = "secs")]
+    pub window: Duration,
+}
+
+/// A downstream service allowed to use the proxy
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Consumer {
+    pub name: String,
+    pub token: String,
+    /// No limit if absent
+    #[serde(default)]
+    pub quota: Option<Quota>,
+}
+
+/// Consumers and cache settings of a [`Proxy`]
+/// # Example usage
+/// ```
+/// use std::time::Duration;
+/// use weather_union::proxy::{Consumer, ProxyConfig, Quota};
+/// let config = ProxyConfig::from_toml(r#"
+///     cache_ttl_secs = 300
+///     [[consumer]]
+///     name = "billing"
+///     token = "billing-token"
+///     quota = { requests = 1000, window_secs = 86400 }
+/// "#).unwrap();
+/// let config = config.consumer(Consumer { name: "maps".into(), token: "maps-token".into(), quota: None });
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyConfig {
+    #[serde(rename = "cache_ttl_secs", with = "secs", default = "default_ttl")]
+    pub cache_ttl: Duration,
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+    #[serde(rename = "consumer", default)]
+    pub consumers: Vec<Consumer>,
+}
+
+fn default_ttl() -> Duration {
+    Duration::from_secs(120)
+}
+
+fn default_max_entries() -> usize {
+    10_000
+}
+
+impl Default for ProxyConfig {
+    fn default() -> ProxyConfig {
+        ProxyConfig { cache_ttl: default_ttl(), max_entries: default_max_entries(), consumers: Vec::new() }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Toml(toml::de::Error),
+    /// Quotas are kept per consumer name
+    DuplicateName(String),
+    DuplicateToken(String),
+    EmptyToken(String),
+}
+
+impl ProxyConfig {
+
+    /// No consumers, responses cached for two minutes
+    pub fn new() -> ProxyConfig {
+        ProxyConfig::default()
+    }
+
+    pub fn from_toml(text: &str) -> Result<ProxyConfig, ConfigError> {
+        let config: ProxyConfig = toml::from_str(text).map_err(ConfigError::Toml)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn cache_ttl(mut self, ttl: Duration) -> ProxyConfig {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    pub fn consumer(mut self, consumer: Consumer) -> ProxyConfig {
+        self.consumers.push(consumer);
+        self
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut names = std::collections::HashSet::new();
+        let mut tokens = std::collections::HashSet::new();
+        for consumer in &self.consumers {
+            if consumer.token.trim().is_empty() {
+                return Err(ConfigError::EmptyToken(consumer.name.clone()))
+            }
+            if !names.insert(consumer.name.as_str()) {
+                return Err(ConfigError::DuplicateName(consumer.name.clone()))
+            }
+            if !tokens.insert(consumer.token.as_str()) {
+                return Err(ConfigError::DuplicateToken(consumer.name.clone()))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Cache and upstream counters of a [`Proxy`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProxyStats {
+    pub cache_hits: u64,
+    /// Requests sent upstream
+    pub cache_misses: u64,
+    /// Misses answered by an upstream request already in flight for the same query
+    pub coalesced: u64,
+    pub rejected: u64,
+    pub over_quota: u64,
+}
+
+type Reply = (StatusCode, HeaderMap, String);
+
+struct Cached {
+    status: StatusCode,
+    body: String,
+    at: Instant,
+}
+
+/// An upstream request shared by every miss for its query, see [`fetch`]
+type Fetch = futures_util::future::Shared<BoxFuture<'static, (StatusCode, String, bool)>>;
+
+struct Usage {
+    window_start: Instant,
+    requests: u64,
+}
+
+struct Shared {
+    client: WeatherUnion,
+    config: ProxyConfig,
+    cache: Mutex<HashMap<String, Cached>>,
+    /// Upstream requests in flight by cache key
+    inflight: Mutex<HashMap<String, Fetch>>,
+    usage: Mutex<HashMap<String, Usage>>,
+    stats: Mutex<ProxyStats>,
+}
+
+/// Serves the WeatherUnion endpoints to consumers holding a proxy token, answering from
+/// a shared cache and sending misses upstream through one [`WeatherUnion`] client.\
+/// Consumers send their token in the `x-zomato-api-key` header, so the crate's own client works unchanged.
+/// Concurrent misses for the same query wait for a single upstream request, and only requests
+/// that go upstream count against a consumer's quota.
+/// # Example usage
+/// ```
+/// use weather_union::WeatherUnion;
+/// use weather_union::proxy::{Proxy, ProxyConfig};
+/// async fn example(){
+///     let config = ProxyConfig::from_toml(&std::fs::read_to_string("proxy.toml").unwrap()).unwrap();
+///     let upstream = WeatherUnion::from_key("api_key".to_string());
+///     let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
+///     Proxy::new(upstream, config).serve(listener).await.unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Proxy {
+    shared: Arc<Shared>,
+}
+
+impl Proxy {
+
+    pub fn new(client: WeatherUnion, config: ProxyConfig) -> Proxy {
+        Proxy {
+            shared: Arc::new(Shared {
+                client,
+                config,
+                cache: Mutex::new(HashMap::new()),
+                inflight: Mutex::new(HashMap::new()),
+                usage: Mutex::new(HashMap::new()),
+                stats: Mutex::new(ProxyStats::default()),
+            }),
+        }
+    }
+
+    /// Routes of the two WeatherUnion endpoints, to mount in a larger server
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/gw/weather/external/v0/get_weather_data", get(lat_long))
+            .route("/gw/weather/external/v0/get_locality_weather_data", get(locality))
+            .with_state(self.shared.clone())
+    }
+
+    /// Serve until the listener fails
+    pub async fn serve(self, listener: tokio::net::TcpListener) -> std::io::Result<()> {
+        axum::serve(listener, self.router()).await
+    }
+
+    pub fn stats(&self) -> ProxyStats {
+        lock(&self.shared.stats).clone()
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn reply(status: StatusCode, cache: &'static str, body: String) -> Reply {
+    let mut headers = HeaderMap::new();
+    headers.insert(HeaderName::from_static("x-cache"), HeaderValue::from_static(cache));
+    (status, headers, body)
+}
+
+fn error(status: StatusCode, message: &str) -> Reply {
+    reply(status, "none", json!({ "status": status.as_u16().to_string(), "message": message }).to_string())
+}
+
+/// The answer WeatherUnion would have given and whether it may be cached.\
+/// Upstream key problems become 503 or 502 so consumers do not mistake them for problems with their own token.
+fn upstream_reply(result: Result<Reading, WeatherResponseError>) -> (StatusCode, String, bool) {
+    let error = |status: StatusCode, message: &str| json!({ "status": status.as_u16().to_string(), "message": message }).to_string();
+    match result {
+        // Readings and unsupported queries stay valid, errors and maintenance messages do not
+        Ok(reading) => (StatusCode::OK, serde_json::to_string(&BodyValues::from(&reading)).unwrap_or_default(), true),
+        Err(WeatherResponseError::NotSupported) => (StatusCode::BAD_REQUEST, error(StatusCode::BAD_REQUEST, "Not supported"), true),
+        Err(WeatherResponseError::TemporarilyUnavailable(message)) => {
+            let body = BodyValues { message, locality_weather_data: HashMap::new(), device_type: 0 };
+            (StatusCode::OK, serde_json::to_string(&body).unwrap_or_default(), false)
+        }
+        Err(WeatherResponseError::ErrorRetrievingData) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, error(StatusCode::INTERNAL_SERVER_ERROR, "Error retrieving data"), false)
+        }
+        Err(WeatherResponseError::ApiKeyLimitExhausted) => {
+            (StatusCode::SERVICE_UNAVAILABLE, error(StatusCode::SERVICE_UNAVAILABLE, "Upstream API key limit exhausted"), false)
+        }
+        Err(other) => (StatusCode::BAD_GATEWAY, error(StatusCode::BAD_GATEWAY, other.variant()), false),
+    }
+}
+
+/// Seconds until the first upstream key comes out of its cooldown
+fn retry_after(client: &WeatherUnion) -> u64 {
+    client.key_stats().iter()
+        .map(|stats| stats.cooldown.map_or(0, |cooldown| cooldown.as_secs() + u64::from(cooldown.subsec_nanos() > 0)))
+        .min()
+        .unwrap_or(0)
+        .max(1)
+}
+
+/// The consumer holding the token of the request.\
+/// Tokens are compared in constant time and against every consumer, so timing does not tell how much of one matched.
+fn authenticate<'a>(shared: &'a Shared, headers: &HeaderMap) -> Result<&'a Consumer, (StatusCode, &'static str)> {
+    let token = headers.get("x-zomato-api-key").and_then(|t| t.to_str().ok()).unwrap_or("");
+    let matching = shared.config.consumers.iter()
+        .fold(None, |found, c| if bool::from(c.token.as_bytes().ct_eq(token.as_bytes())) { Some(c) } else { found });
+    matching.filter(|_| !token.is_empty()).ok_or_else(|| {
+        lock(&shared.stats).rejected += 1;
+        (StatusCode::FORBIDDEN, "Invalid API key")
+    })
+}
+
+/// Charge the consumer's quota for an upstream request
+fn charge(shared: &Shared, consumer: &Consumer) -> Result<(), (StatusCode, &'static str)> {
+    let Some(quota) = consumer.quota else { return Ok(()) };
+    let now = Instant::now();
+    let mut usage = lock(&shared.usage);
+    let usage = usage.entry(consumer.name.clone()).or_insert(Usage { window_start: now, requests: 0 });
+    if now.duration_since(usage.window_start) >= quota.window {
+        *usage = Usage { window_start: now, requests: 0 };
+    }
+    if usage.requests >= quota.requests {
+        lock(&shared.stats).over_quota += 1;
+        return Err((StatusCode::TOO_MANY_REQUESTS, "API key limit exhausted"))
+    }
+    usage.requests += 1;
+    Ok(())
+}
+
+/// Send a request upstream, caching the answer if it may be and then leaving the in-flight table,
+/// in that order so a miss always finds either the answer or the request.
+fn fetch(shared: Arc<Shared>, cache_key: String,
+         upstream: impl Future<Output = Result<Reading, WeatherResponseError>> + Send + 'static) -> Fetch {
+    async move {
+        let (status, body, cacheable) = upstream_reply(upstream.await);
+        if cacheable {
+            let ttl = shared.config.cache_ttl;
+            let mut cache = lock(&shared.cache);
+            if cache.len() >= shared.config.max_entries {
+                cache.retain(|_, c| c.at.elapsed() < ttl);
+            }
+            if cache.len() < shared.config.max_entries {
+                cache.insert(cache_key.clone(), Cached { status, body: body.clone(), at: Instant::now() });
+            }
+        }
+        lock(&shared.inflight).remove(&cache_key);
+        (status, body, cacheable)
+    }.boxed().shared()
+}
+
+async fn respond(shared: Arc<Shared>, headers: HeaderMap, cache_key: String,
+                 upstream: impl Future<Output = Result<Reading, WeatherResponseError>> + Send + 'static) -> Reply {
+    let consumer = match authenticate(&shared, &headers) {
+        Ok(consumer) => consumer,
+        Err((status, message)) => return error(status, message),
+    };
+    let ttl = shared.config.cache_ttl;
+    let pending = {
+        let mut inflight = lock(&shared.inflight);
+        if let Some(cached) = lock(&shared.cache).get(&cache_key).filter(|c| c.at.elapsed() < ttl) {
+            lock(&shared.stats).cache_hits += 1;
+            return reply(cached.status, "hit", cached.body.clone())
+        }
+        match inflight.get(&cache_key) {
+            Some(pending) => {
+                lock(&shared.stats).coalesced += 1;
+                pending.clone()
+            }
+            None => {
+                if let Err((status, message)) = charge(&shared, consumer) {
+                    return error(status, message)
+                }
+                lock(&shared.stats).cache_misses += 1;
+                let pending = fetch(shared.clone(), cache_key.clone(), upstream);
+                inflight.insert(cache_key, pending.clone());
+                pending
+            }
+        }
+    };
+    let (status, body, _) = pending.await;
+    let mut answer = reply(status, "miss", body);
+    if status == StatusCode::SERVICE_UNAVAILABLE {
+        answer.1.insert(RETRY_AFTER, HeaderValue::from(retry_after(&shared.client)));
+    }
+    answer
+}
+
+async fn locality(State(shared): State<Arc<Shared>>, headers: HeaderMap,
+                  Query(query): Query<HashMap<String, String>>) -> Reply {
+    let Some(id) = query.get("locality_id").cloned() else {
+        return error(StatusCode::BAD_REQUEST, "Not supported")
+    };
+    let upstream = shared.clone();
+    let key = format!("locality:{}", id);
+    respond(shared, headers, key, async move { upstream.client.locality_id_reading(&id).await }).await
+}
+
+async fn lat_long(State(shared): State<Arc<Shared>>, headers: HeaderMap,
+                  Query(query): Query<HashMap<String, String>>) -> Reply {
+    let lat = query.get("latitude").and_then(|v| v.parse::<f64>().ok());
+    let long = query.get("longitude").and_then(|v| v.parse::<f64>().ok());
+    let Some((lat, long)) = lat.zip(long) else {
+        return error(StatusCode::BAD_REQUEST, "Not supported")
+    };
+    let upstream = shared.clone();
+    let key = format!("lat_long:{}:{}", lat, long);
+    respond(shared, headers, key, async move { upstream.client.lat_long_reading(lat, long).await }).await
+}
+
+mod secs {
+    use std::time::Duration;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_secs(u64::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{FakeTransport, HttpResponse};
+    use crate::LocalityId;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    const READING: &str = r#"{"status":"200","message":"","device_type":1,"locality_weather_data":{"temperature":24.5,"humidity":60.0,"wind_speed":1.5,"wind_direction":90.0,"rain_intensity":0.0,"rain_accumulation":2.0}}"#;
+
+    fn config() -> ProxyConfig {
+        ProxyConfig::from_toml(r#"
+            cache_ttl_secs = 60
+            [[consumer]]
+            name = "maps"
+            token = "maps-token"
+            [[consumer]]
+            name = "billing"
+            token = "billing-token"
+            quota = { requests = 2, window_secs = 3600 }
+        "#).unwrap()
+    }
+
+    async fn start(proxy: &Proxy) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(proxy.clone().serve(listener));
+        url
+    }
+
+    #[test]
+    fn test_config() {
+        let config = config();
+        assert_eq!(config.cache_ttl, Duration::from_secs(60));
+        assert_eq!(config.consumers[0].quota, None);
+        assert_eq!(config.consumers[1].quota, Some(Quota { requests: 2, window: Duration::from_secs(3600) }));
+        let duplicate = "[[consumer]]\nname = \"a\"\ntoken = \"t\"\n[[consumer]]\nname = \"b\"\ntoken = \"t\"";
+        assert!(matches!(ProxyConfig::from_toml(duplicate), Err(ConfigError::DuplicateToken(name)) if name == "b"));
+        assert!(matches!(ProxyConfig::from_toml("[[consumer]]\nname = \"a\"\ntoken = \" \""), Err(ConfigError::EmptyToken(_))));
+        let same_name = "[[consumer]]\nname = \"a\"\ntoken = \"t\"\n[[consumer]]\nname = \"a\"\ntoken = \"u\"";
+        assert!(matches!(ProxyConfig::from_toml(same_name), Err(ConfigError::DuplicateName(name)) if name == "a"));
+        // Misspelt settings are errors rather than silently defaulted
+        assert!(matches!(ProxyConfig::from_toml("cache_ttl = 60"), Err(ConfigError::Toml(_))));
+        let quota = "[[consumer]]\nname = \"a\"\ntoken = \"t\"\nquota = { requests = 1, window = 60 }";
+        assert!(matches!(ProxyConfig::from_toml(quota), Err(ConfigError::Toml(_))));
+    }
+
+    #[test]
+    fn test_cache_tokens_and_quotas() {
+        aw!(async {
+            let fake = Arc::new(FakeTransport::new());
+            fake.push(HttpResponse::new(200, READING));
+            fake.push(HttpResponse::new(500, r#"{"status":"500","message":"Error retrieving data"}"#));
+            let upstream = WeatherUnion::from_key("upstream_key".to_string()).with_transport(fake.clone());
+            let proxy = Proxy::new(upstream, config());
+            let url = start(&proxy).await;
+
+            let maps = WeatherUnion::from_key("maps-token".to_string()).with_base_url(&url);
+            let first = maps.locality(LocalityId::ZWL005764).await.unwrap();
+            assert_eq!(first.temperature, 24.5);
+            assert_eq!(maps.locality_id("ZWL005764").await.unwrap(), first);
+            // Errors are passed on but not cached
+            assert!(matches!(maps.lat_long(28.5, 77.2).await, Err(WeatherResponseError::ErrorRetrievingData)));
+            assert!(maps.lat_long(28.5, 77.2).await.is_err());
+            let requests = fake.requests();
+            assert_eq!(requests.len(), 3);
+            assert_eq!(requests[0].header_value("x-zomato-api-key"), Some("upstream_key"));
+
+            let stranger = WeatherUnion::from_key("stranger".to_string()).with_base_url(&url);
+            assert!(matches!(stranger.locality(LocalityId::ZWL005764).await, Err(WeatherResponseError::CouldNotAuthenticate)));
+
+            // Cache hits are free, the quota only covers requests sent upstream
+            let billing = WeatherUnion::from_key("billing-token".to_string()).with_base_url(&url);
+            for _ in 0..3 {
+                assert!(billing.locality(LocalityId::ZWL005764).await.is_ok());
+            }
+            fake.push(HttpResponse::new(200, READING));
+            fake.push(HttpResponse::new(200, READING));
+            assert!(billing.locality(LocalityId::ZWL003467).await.is_ok());
+            assert!(billing.lat_long(12.9, 77.5).await.is_ok());
+            assert!(matches!(billing.locality(LocalityId::ZWL008436).await, Err(WeatherResponseError::ApiKeyLimitExhausted)));
+            assert_eq!(fake.requests().len(), 5);
+
+            assert_eq!(proxy.stats(), ProxyStats { cache_hits: 4, cache_misses: 5, coalesced: 0, rejected: 1, over_quota: 1 });
+        });
+    }
+
+    #[test]
+    fn test_upstream_limit_is_not_the_consumers() {
+        aw!(async {
+            let fake = Arc::new(FakeTransport::new());
+            fake.push(HttpResponse::new(429, ""));
+            fake.push(HttpResponse::new(429, ""));
+            let upstream = WeatherUnion::from_key("upstream_key".to_string()).with_transport(fake.clone());
+            let proxy = Proxy::new(upstream, config());
+            let url = start(&proxy).await;
+
+            let maps = WeatherUnion::from_key("maps-token".to_string()).with_base_url(&url);
+            assert!(matches!(maps.locality(LocalityId::ZWL005764).await, Err(WeatherResponseError::UnknownError(StatusCode::SERVICE_UNAVAILABLE))));
+            // The consumer's own token does not cool down
+            assert_eq!(maps.key_stats()[0].cooldown, None);
+
+            let response = reqwest::Client::new()
+                .get(format!("{}/gw/weather/external/v0/get_locality_weather_data?locality_id=ZWL005764", url))
+                .header("x-zomato-api-key", "maps-token")
+                .send().await.unwrap();
+            assert_eq!(response.status().as_u16(), 503);
+            let retry_after: u64 = response.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+            assert!((1..=60).contains(&retry_after));
+        });
+    }
+
+    /// Answers like the wrapped fake once the gate is opened
+    struct Gated(Arc<FakeTransport>, Arc<tokio::sync::Notify>);
+
+    impl crate::transport::Transport for Gated {
+        fn send(&self, request: crate::transport::HttpRequest) -> crate::transport::TransportFuture<'_> {
+            let response = self.0.send(request);
+            Box::pin(async move {
+                self.1.notified().await;
+                response.await
+            })
+        }
+    }
+
+    #[test]
+    fn test_concurrent_misses_share_one_request() {
+        aw!(async {
+            let fake = Arc::new(FakeTransport::new());
+            fake.push(HttpResponse::new(200, READING));
+            let gate = Arc::new(tokio::sync::Notify::new());
+            let upstream = WeatherUnion::from_key("upstream_key".to_string()).with_transport(Gated(fake.clone(), gate.clone()));
+            let proxy = Proxy::new(upstream, config());
+            let url = start(&proxy).await;
+            let maps = WeatherUnion::from_key("maps-token".to_string()).with_base_url(&url);
+            let billing = WeatherUnion::from_key("billing-token".to_string()).with_base_url(&url);
+            let requests = (0..4).map(|i| if i == 0 { &billing } else { &maps }).map(|client| client.locality(LocalityId::ZWL005764));
+            // The upstream answer is held back until every request is waiting on it
+            let (results, _) = tokio::join!(futures_util::future::join_all(requests), async {
+                while proxy.stats().cache_misses + proxy.stats().coalesced < 4 {
+                    tokio::task::yield_now().await;
+                }
+                gate.notify_one();
+            });
+            assert!(results.iter().all(|result| result.as_ref().is_ok_and(|data| data.temperature == 24.5)));
+            assert_eq!(fake.requests().len(), 1);
+            let stats = proxy.stats();
+            assert_eq!((stats.cache_misses, stats.coalesced), (1, 3));
+        });
+    }
+
+    #[test]
+    fn test_cache_expires() {
+        aw!(async {
+            tokio::time::pause();
+            let fake = Arc::new(FakeTransport::new());
+            fake.push(HttpResponse::new(200, READING));
+            fake.push(HttpResponse::new(200, READING));
+            let upstream = WeatherUnion::from_key("upstream_key".to_string()).with_transport(fake.clone());
+            let proxy = Proxy::new(upstream, config());
+            let mut headers = HeaderMap::new();
+            headers.insert("x-zomato-api-key", HeaderValue::from_static("maps-token"));
+            let request = || {
+                let query = HashMap::from([("locality_id".to_string(), "ZWL005764".to_string())]);
+                locality(State(proxy.shared.clone()), headers.clone(), Query(query))
+            };
+            assert_eq!(request().await.1["x-cache"], "miss");
+            tokio::time::advance(Duration::from_secs(59)).await;
+            assert_eq!(request().await.1["x-cache"], "hit");
+            tokio::time::advance(Duration::from_secs(1)).await;
+            assert_eq!(request().await.1["x-cache"], "miss");
+            assert_eq!(fake.requests().len(), 2);
+        });
+    }
+}
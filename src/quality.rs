@@ -0,0 +1,411 @@
+use core::fmt;
+use std::collections::BTreeMap;
+use std::fmt::Formatter;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Metric, Reading};
+
+/// What to do with a value that fails a check
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Keep the value and report it
+    Tag,
+    /// Replace the value with the nearest allowed one, 0 for a metric the device should not report.\
+    /// Missing values are left missing.
+    Clamp,
+    /// Report the value and mark the whole reading as rejected
+    Reject,
+}
+
+/// Allowed range of a metric and what to do outside of it
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MetricCheck {
+    pub min: f64,
+    pub max: f64,
+    pub action: Action,
+}
+
+impl MetricCheck {
+
+    /// Physical bounds of a metric in the units the API reports, values outside them are sensor faults.
+    pub fn physical(metric: Metric) -> MetricCheck {
+        let (min, max) = match metric {
+            Metric::Temperature => (-40.0, 60.0),
+            Metric::Humidity => (0.0, 100.0),
+            Metric::WindSpeed => (0.0, 75.0),
+            Metric::WindDirection => (0.0, 360.0),
+            // mm per minute, 10 is beyond any recorded cloudburst
+            Metric::RainIntensity => (0.0, 10.0),
+            // mm since midnight
+            Metric::RainAccumulation => (0.0, 2000.0),
+        };
+        MetricCheck { min, max, action: Action::Tag }
+    }
+}
+
+/// Why a value is suspect
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum Reason {
+    BelowMin { min: f64 },
+    AboveMax { max: f64 },
+    /// Left out of the reading although the device measures it, see [`Reading`]
+    Missing,
+    /// Infinite
+    NotFinite,
+    /// A value other than 0 for a metric the device does not measure, e.g. temperature from a rain gauge
+    NotMeasured { device: u8 },
+    /// The reading's device type is neither 1 nor 2
+    UnknownDevice { device: u8 },
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::BelowMin { min } => write!(f, "below the minimum of {}", min),
+            Reason::AboveMax { max } => write!(f, "above the maximum of {}", max),
+            Reason::Missing => f.write_str("missing"),
+            Reason::NotFinite => f.write_str("not a finite number"),
+            Reason::NotMeasured { device } => write!(f, "not measured by device type {}", device),
+            Reason::UnknownDevice { device } => write!(f, "unknown device type {}", device),
+        }
+    }
+}
+
+/// A suspect field of a reading, `metric` is None when the reading as a whole is suspect.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Finding {
+    pub metric: Option<Metric>,
+    /// The value as received
+    pub value: f64,
+    pub reason: Reason,
+    pub action: Action,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.metric {
+            Some(metric) => write!(f, "{} {} {}", metric, self.value, self.reason),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+/// A reading after validation, with clamped values applied
+#[derive(Clone, Debug, PartialEq)]
+pub struct Validated {
+    pub reading: Reading,
+    pub findings: Vec<Finding>,
+}
+
+impl Validated {
+
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// True if any finding asks for the reading to be dropped
+    pub fn is_rejected(&self) -> bool {
+        self.findings.iter().any(|finding| finding.action == Action::Reject)
+    }
+}
+
+/// Bounds that are not finite or have `min` above `max`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvalidBounds {
+    pub metric: Metric,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl fmt::Display for InvalidBounds {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} needs finite bounds with min <= max, got {} to {}", self.metric, self.min, self.max)
+    }
+}
+
+#[derive(Debug)]
+pub enum ValidatorParseError {
+    Toml(toml::de::Error), Invalid(InvalidBounds)
+}
+
+impl fmt::Display for ValidatorParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidatorParseError::Toml(e) => write!(f, "invalid validator config: {}", e),
+            ValidatorParseError::Invalid(e) => e.fmt(f),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CheckOverride {
+    min: Option<f64>,
+    max: Option<f64>,
+    action: Option<Action>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ValidatorConfig {
+    #[serde(default)]
+    metrics: BTreeMap<Metric, CheckOverride>,
+    /// Action for values a device should not report and for unknown device types
+    device_action: Option<Action>,
+    /// Action for metrics the device measures but left out of the reading
+    missing_action: Option<Action>,
+}
+
+/// Checks readings against physical bounds and what each device type measures
+/// # Example usage
+/// ```
+/// use weather_union::quality::{Action, Validator};
+/// use weather_union::{LocalityId, Metric, WeatherUnion};
+/// async fn example(){
+///     let validator = Validator::from_toml(r#"
+///         device_action = "clamp"
+///         missing_action = "reject"   # drop readings with a gap
+///         [metrics.humidity]
+///         action = "clamp"            # 104% becomes 100%
+///         [metrics.temperature]
+///         min = -5.0                  # tighter than the physical bounds
+///         max = 52.0
+///         action = "reject"
+///     "#).unwrap();
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let checked = validator.validate(&client.locality_reading(LocalityId::ZWL005764).await.unwrap());
+///     for finding in &checked.findings {
+///         println!("{} ({:?})", finding, finding.action);
+///     }
+///     if !checked.is_rejected() {
+///         println!("{}", checked.reading.data.humidity);
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Validator {
+    checks: BTreeMap<Metric, MetricCheck>,
+    device_action: Action,
+    missing_action: Action,
+}
+
+impl Default for Validator {
+    fn default() -> Validator {
+        Validator {
+            checks: Metric::ALL.iter().map(|metric| (*metric, MetricCheck::physical(*metric))).collect(),
+            device_action: Action::Tag,
+            missing_action: Action::Tag,
+        }
+    }
+}
+
+impl Validator {
+
+    /// Physical bounds for every metric, suspect values are only tagged.
+    pub fn new() -> Validator {
+        Validator::default()
+    }
+
+    /// Start from [`Validator::new`] and apply overrides from a TOML document, see the example above.
+    pub fn from_toml(source: &str) -> Result<Validator, ValidatorParseError> {
+        let config: ValidatorConfig = toml::from_str(source).map_err(ValidatorParseError::Toml)?;
+        let mut validator = Validator::new();
+        if let Some(action) = config.device_action {
+            validator.device_action = action;
+        }
+        if let Some(action) = config.missing_action {
+            validator.missing_action = action;
+        }
+        for (metric, check) in config.metrics {
+            let current = validator.checks[&metric];
+            validator = validator
+                .check(metric, MetricCheck {
+                    min: check.min.unwrap_or(current.min),
+                    max: check.max.unwrap_or(current.max),
+                    action: check.action.unwrap_or(current.action),
+                })
+                .map_err(ValidatorParseError::Invalid)?;
+        }
+        Ok(validator)
+    }
+
+    /// Replace the check of a metric, the bounds must be finite with `min <= max`.
+    pub fn check(mut self, metric: Metric, check: MetricCheck) -> Result<Validator, InvalidBounds> {
+        if !(check.min.is_finite() && check.max.is_finite() && check.min <= check.max) {
+            return Err(InvalidBounds { metric, min: check.min, max: check.max })
+        }
+        self.checks.insert(metric, check);
+        Ok(self)
+    }
+
+    /// Change the action of a metric's check, keeping its bounds. It applies to values outside the bounds,
+    /// see [`Validator::missing_action`] for values left out.
+    pub fn action(mut self, metric: Metric, action: Action) -> Validator {
+        if let Some(check) = self.checks.get_mut(&metric) {
+            check.action = action;
+        }
+        self
+    }
+
+    /// Action for values a device should not report and for unknown device types
+    pub fn device_action(mut self, action: Action) -> Validator {
+        self.device_action = action;
+        self
+    }
+
+    /// Action for metrics the device measures but left out of the reading, whatever their check's action.
+    /// Clamping leaves them missing.
+    pub fn missing_action(mut self, action: Action) -> Validator {
+        self.missing_action = action;
+        self
+    }
+
+    pub fn validate(&self, reading: &Reading) -> Validated {
+        let data = &reading.data;
+        let mut checked = *reading;
+        let mut findings = Vec::new();
+        if !matches!(data.device, 1 | 2) {
+            findings.push(Finding {
+                metric: None, value: data.device as f64, reason: Reason::UnknownDevice { device: data.device }, action: self.device_action,
+            });
+        }
+        for metric in Metric::ALL {
+            let value = data.raw(metric);
+            if !metric.is_reported_by(data.device) {
+                if matches!(data.device, 1 | 2) && value != 0.0 {
+                    findings.push(Finding { metric: Some(metric), value, reason: Reason::NotMeasured { device: data.device }, action: self.device_action });
+                    if self.device_action == Action::Clamp {
+                        checked.data.set_raw(metric, 0.0);
+                    }
+                }
+                continue
+            }
+            let check = self.checks[&metric];
+            let reason = if reading.missing.contains(metric) {
+                Reason::Missing
+            } else if !value.is_finite() {
+                Reason::NotFinite
+            } else if value < check.min {
+                Reason::BelowMin { min: check.min }
+            } else if value > check.max {
+                Reason::AboveMax { max: check.max }
+            } else {
+                continue
+            };
+            let action = if reason == Reason::Missing { self.missing_action } else { check.action };
+            findings.push(Finding { metric: Some(metric), value, reason, action });
+            // A missing value stays missing, NaN has no nearest bound and falls back to the value closest to 0
+            if action == Action::Clamp && reason != Reason::Missing {
+                let clamped = if value.is_nan() { 0.0_f64.clamp(check.min, check.max) } else { value.clamp(check.min, check.max) };
+                checked.data.set_raw(metric, clamped);
+            }
+        }
+        Validated { reading: checked, findings }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aws, LocalityWeatherData};
+
+    #[test]
+    fn test_bounds_and_actions() {
+        assert!(Validator::new().validate(&aws(24.5).into()).is_clean());
+
+        let broken = LocalityWeatherData { humidity: 104.0, wind_direction: 400.0, rain_accumulation: -1.0, ..aws(24.5) }.into();
+        let tagged = Validator::new().validate(&broken);
+        assert_eq!(tagged.reading, broken);
+        assert!(!tagged.is_rejected());
+        let reasons: Vec<(Option<Metric>, Reason)> = tagged.findings.iter().map(|f| (f.metric, f.reason)).collect();
+        assert_eq!(reasons, [
+            (Some(Metric::Humidity), Reason::AboveMax { max: 100.0 }),
+            (Some(Metric::WindDirection), Reason::AboveMax { max: 360.0 }),
+            (Some(Metric::RainAccumulation), Reason::BelowMin { min: 0.0 }),
+        ]);
+        assert_eq!(tagged.findings[0].to_string(), "humidity 104 above the maximum of 100");
+
+        let clamped = Validator::new().action(Metric::Humidity, Action::Clamp).action(Metric::RainAccumulation, Action::Clamp).validate(&broken);
+        assert_eq!(clamped.reading.data.humidity, 100.0);
+        assert_eq!(clamped.reading.data.rain_accumulation, 0.0);
+        assert_eq!(clamped.reading.data.wind_direction, 400.0);
+        assert_eq!(clamped.findings.len(), 3);
+
+        let rejected = Validator::new().action(Metric::WindDirection, Action::Reject).validate(&broken);
+        assert!(rejected.is_rejected());
+
+        let clamp = Validator::new().action(Metric::Temperature, Action::Clamp);
+        let gap = Reading::from_metrics(1, |metric| aws(24.5).metric(metric).filter(|_| metric != Metric::Temperature));
+        let missing = clamp.validate(&gap);
+        assert_eq!(missing.findings[0].reason, Reason::Missing);
+        assert_eq!(missing.findings[0].action, Action::Tag);
+        assert_eq!(missing.reading.metric(Metric::Temperature), None);
+        // Range actions leave gaps alone, they have their own action
+        assert!(!Validator::new().action(Metric::Temperature, Action::Reject).validate(&gap).is_rejected());
+        let strict = Validator::new().missing_action(Action::Reject);
+        assert!(strict.validate(&gap).is_rejected());
+        assert!(!strict.validate(&aws(24.5).into()).is_rejected());
+        assert_eq!(clamp.clone().missing_action(Action::Clamp).validate(&gap).reading.metric(Metric::Temperature), None);
+        let nan = clamp.validate(&LocalityWeatherData { temperature: f64::NAN, ..aws(24.5) }.into());
+        assert_eq!(nan.findings[0].reason, Reason::NotFinite);
+        assert_eq!(nan.reading.data.temperature, 0.0);
+        let infinite = clamp.validate(&LocalityWeatherData { temperature: f64::INFINITY, ..aws(24.5) }.into());
+        assert_eq!(infinite.findings[0].reason, Reason::NotFinite);
+        assert_eq!(infinite.reading.data.temperature, 60.0);
+    }
+
+    #[test]
+    fn test_device_expectations() {
+        let rgs = LocalityWeatherData { device: 2, temperature: 31.0, humidity: 0.0, wind_speed: 0.0, wind_direction: 0.0, ..aws(24.5) };
+        let tagged = Validator::new().validate(&rgs.into());
+        assert_eq!(tagged.findings.len(), 1);
+        assert_eq!(tagged.findings[0].reason, Reason::NotMeasured { device: 2 });
+        assert_eq!(Validator::new().device_action(Action::Clamp).validate(&rgs.into()).reading.data.temperature, 0.0);
+        // Bounds of unmeasured metrics are not checked
+        let rgs = LocalityWeatherData { humidity: 0.0, temperature: 0.0, ..rgs };
+        let strict = Validator::new().check(Metric::Temperature, MetricCheck { min: 10.0, max: 20.0, action: Action::Reject }).unwrap();
+        assert!(strict.validate(&rgs.into()).is_clean());
+
+        let unknown = Validator::new().device_action(Action::Reject).validate(&LocalityWeatherData { device: 0, ..aws(24.5) }.into());
+        assert!(unknown.is_rejected());
+        assert_eq!(unknown.findings[0].metric, None);
+    }
+
+    #[test]
+    fn test_invalid_bounds() {
+        let inverted = MetricCheck { min: 100.0, max: 0.0, action: Action::Clamp };
+        assert_eq!(Validator::new().check(Metric::Humidity, inverted), Err(InvalidBounds { metric: Metric::Humidity, min: 100.0, max: 0.0 }));
+        assert!(Validator::new().check(Metric::Humidity, MetricCheck { min: f64::NAN, ..inverted }).is_err());
+        assert!(Validator::new().check(Metric::Humidity, MetricCheck { max: f64::INFINITY, ..inverted }).is_err());
+        // A single point is a valid range
+        let pinned = Validator::new().check(Metric::Humidity, MetricCheck { min: 50.0, max: 50.0, action: Action::Clamp }).unwrap();
+        assert_eq!(pinned.validate(&aws(24.5).into()).reading.data.humidity, 50.0);
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let validator = Validator::from_toml(r#"
+            device_action = "reject"
+            missing_action = "reject"
+            [metrics.humidity]
+            action = "clamp"
+            [metrics.temperature]
+            min = -5.0
+            max = 52.0
+        "#).unwrap();
+        assert_eq!(validator.checks[&Metric::Humidity], MetricCheck { min: 0.0, max: 100.0, action: Action::Clamp });
+        assert_eq!(validator.checks[&Metric::Temperature], MetricCheck { min: -5.0, max: 52.0, action: Action::Tag });
+        assert_eq!(validator.device_action, Action::Reject);
+        assert_eq!(validator.missing_action, Action::Reject);
+        assert_eq!(validator.validate(&LocalityWeatherData { temperature: 53.0, ..aws(24.5) }.into()).findings.len(), 1);
+
+        let inverted = Validator::from_toml("[metrics.humidity]\nmin = 50.0\nmax = 10.0").unwrap_err();
+        assert!(matches!(inverted, ValidatorParseError::Invalid(InvalidBounds { metric: Metric::Humidity, min: 50.0, max: 10.0 })));
+        assert_eq!(inverted.to_string(), "humidity needs finite bounds with min <= max, got 50 to 10");
+        assert!(matches!(Validator::from_toml("[metrics.pressure]\nmax = 10.0"), Err(ValidatorParseError::Toml(_))));
+        assert!(matches!(Validator::from_toml("[metrics.humidity]\nmaximum = 10.0"), Err(ValidatorParseError::Toml(_))));
+    }
+}
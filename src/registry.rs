@@ -0,0 +1,314 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use crate::{city_of, table, InvalidLocalityId, LocalityId};
+
+/// One row of a locality table
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LocalityRecord {
+    pub id: String,
+    pub name: String,
+    pub lat: f64,
+    pub long: f64,
+    pub device_type: u8,
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Io(std::io::Error), Json(serde_json::Error), Csv(csv::Error), Malformed { line: usize, reason: String }
+}
+
+/// Locality metadata loaded at runtime, for stations newer than the built-in [`LocalityId`] constants.\
+/// Loading a registry has no effect outside it. [`LocalityRegistry::install`] makes its ids known to
+/// [`LocalityId::from_str`], and their `locality_name`, `locality_lat_long`, `device_type` and `city`
+/// then report the record installed last, so runtime ids work with [`crate::aggregate::by_city`],
+/// exports and the server like built-in ones.
+/// [`LocalityId::ALL`], [`LocalityId::in_city`] and [`LocalityId::search`] only cover the built-in table,
+/// and built-in ids keep their built-in metadata, use [`LocalityRegistry::ids`] and its getters for those.
+/// # Example usage
+/// ```
+/// use weather_union::registry::LocalityRegistry;
+/// use weather_union::WeatherUnion;
+/// async fn example(){
+///     // Built-in table, updated with a freshly downloaded list
+///     let mut registry = LocalityRegistry::builtin();
+///     registry.merge(LocalityRegistry::from_path("localities.txt").unwrap());
+///     registry.install();
+///     let id = registry.locality("ZWL008436").unwrap();
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let data = client.locality(id).await;
+///     println!("{:?} {:?}", registry.name(id), data);
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LocalityRegistry {
+    records: BTreeMap<&'static str, LocalityRecord>,
+}
+
+impl LocalityRegistry {
+
+    /// An empty registry
+    pub fn new() -> LocalityRegistry {
+        LocalityRegistry::default()
+    }
+
+    /// Registry holding the built-in [`LocalityId`] table
+    pub fn builtin() -> LocalityRegistry {
+        LocalityRegistry::from_records(LocalityId::ALL.iter().filter_map(|id| {
+            let (lat, long) = id.locality_lat_long()?;
+            Some(LocalityRecord {
+                id: id.0.to_string(),
+                name: id.locality_name()?.to_string(),
+                lat, long,
+                device_type: id.device_type()?,
+            })
+        })).expect("built-in localities are valid")
+    }
+
+    /// Build from records, rejecting malformed ids, coordinates outside India and unknown device types.\
+    /// Later records replace earlier ones with the same id.
+    pub fn from_records(records: impl IntoIterator<Item = LocalityRecord>) -> Result<LocalityRegistry, RegistryError> {
+        let records: Vec<LocalityRecord> = records.into_iter().collect();
+        for (index, record) in records.iter().enumerate() {
+            validate(record).map_err(|reason| RegistryError::Malformed { line: index + 1, reason })?;
+        }
+        let mut registry = LocalityRegistry::new();
+        for record in records {
+            registry.records.insert(intern_id(&record.id), record);
+        }
+        Ok(registry)
+    }
+
+    /// Make the ids of this registry known to [`LocalityId::from_str`] and the [`LocalityId`] getters
+    /// for the rest of the process, replacing the metadata of ids installed before.\
+    /// Built-in ids keep their built-in metadata.
+    pub fn install(&self) {
+        let mut runtime = runtime();
+        for (id, record) in &self.records {
+            if crate::builtin(id).is_some() {
+                continue
+            }
+            let name = runtime.intern(&record.name);
+            let locality = RuntimeLocality { id, name, lat_long: (record.lat, record.long), device_type: record.device_type };
+            runtime.localities.insert(id, locality);
+        }
+    }
+
+    /// Parse the `localities.txt` format, one `<name> <id> <lat> <long> <device type> - <description>` per line.
+    /// # Example
+    /// ```
+    ///  use weather_union::registry::LocalityRegistry;
+    ///  let registry = LocalityRegistry::from_text("Raipur Moudhapara, Raipur ZWL008436 21.256569 81.627672 2 - Rain gauge system").unwrap();
+    ///  let id = registry.locality("ZWL008436").unwrap();
+    ///  assert_eq!(registry.city(id), Some("Raipur"));
+    /// ```
+    pub fn from_text(source: &str) -> Result<LocalityRegistry, RegistryError> {
+        let mut records = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue
+            }
+            let parsed = table::parse_line(line).map_err(|reason| RegistryError::Malformed { line: index + 1, reason })?;
+            records.push(LocalityRecord {
+                id: parsed.id.to_string(),
+                name: parsed.name.to_string(),
+                lat: parsed.lat,
+                long: parsed.long,
+                device_type: parsed.device_type,
+            });
+        }
+        LocalityRegistry::from_records(records)
+    }
+
+    /// Parse CSV with an `id,name,lat,long,device_type` header.
+    pub fn from_csv(source: &str) -> Result<LocalityRegistry, RegistryError> {
+        let records = csv::Reader::from_reader(source.as_bytes())
+            .deserialize::<LocalityRecord>()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(RegistryError::Csv)?;
+        LocalityRegistry::from_records(records)
+    }
+
+    /// Parse a JSON array of [`LocalityRecord`] objects.
+    pub fn from_json(source: &str) -> Result<LocalityRegistry, RegistryError> {
+        LocalityRegistry::from_records(serde_json::from_str::<Vec<LocalityRecord>>(source).map_err(RegistryError::Json)?)
+    }
+
+    /// Load a file, picking the format from its extension (`csv`, `json`, anything else is the text format).
+    pub fn from_path(path: impl AsRef<Path>) -> Result<LocalityRegistry, RegistryError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(RegistryError::Io)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => LocalityRegistry::from_csv(&source),
+            Some("json") => LocalityRegistry::from_json(&source),
+            _ => LocalityRegistry::from_text(&source),
+        }
+    }
+
+    /// Add every locality of `other`, replacing entries with the same id.
+    pub fn merge(&mut self, other: LocalityRegistry) {
+        self.records.extend(other.records);
+    }
+
+    /// Validate an id against the registry
+    pub fn locality(&self, id: &str) -> Result<LocalityId, InvalidLocalityId> {
+        self.records.get_key_value(id).map(|(id, _)| LocalityId(id)).ok_or(InvalidLocalityId::new())
+    }
+
+    pub fn record(&self, id: LocalityId) -> Option<&LocalityRecord> {
+        self.records.get(id.0)
+    }
+
+    pub fn name(&self, id: LocalityId) -> Option<&str> {
+        self.record(id).map(|r| r.name.as_str())
+    }
+
+    pub fn lat_long(&self, id: LocalityId) -> Option<(f64, f64)> {
+        self.record(id).map(|r| (r.lat, r.long))
+    }
+
+    pub fn device_type(&self, id: LocalityId) -> Option<u8> {
+        self.record(id).map(|r| r.device_type)
+    }
+
+    pub fn city(&self, id: LocalityId) -> Option<&'static str> {
+        city_of(self.name(id)?)
+    }
+
+    /// Every locality in the registry, ordered by id
+    pub fn ids(&self) -> impl Iterator<Item = LocalityId> + '_ {
+        self.records.keys().map(|id| LocalityId(id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+/// Metadata of an id loaded at runtime, as the [`LocalityId`] methods report it
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RuntimeLocality {
+    pub(crate) id: &'static str,
+    pub(crate) name: &'static str,
+    pub(crate) lat_long: (f64, f64),
+    pub(crate) device_type: u8,
+}
+
+#[derive(Default)]
+struct Runtime {
+    /// Every id and name ever loaded, leaked once each
+    strings: HashSet<&'static str>,
+    /// Ids of installed registries
+    localities: HashMap<&'static str, RuntimeLocality>,
+}
+
+impl Runtime {
+    fn intern(&mut self, value: &str) -> &'static str {
+        if let Some(existing) = self.strings.get(value) {
+            return existing
+        }
+        let leaked: &'static str = Box::leak(value.to_string().into_boxed_str());
+        self.strings.insert(leaked);
+        leaked
+    }
+}
+
+fn runtime() -> MutexGuard<'static, Runtime> {
+    static RUNTIME: OnceLock<Mutex<Runtime>> = OnceLock::new();
+    RUNTIME.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Returns the `&'static` form of an id, reusing the built-in constant when there is one.\
+/// Strings are leaked once per distinct id or name and shared by every registry, so loading the same
+/// table again costs nothing and memory only grows with ids and names never seen before.
+fn intern_id(id: &str) -> &'static str {
+    match crate::builtin(id) {
+        Some(builtin) => builtin.0,
+        None => runtime().intern(id),
+    }
+}
+
+/// The last installed metadata of a runtime id
+pub(crate) fn runtime_locality(id: &str) -> Option<RuntimeLocality> {
+    runtime().localities.get(id).copied()
+}
+
+fn validate(record: &LocalityRecord) -> Result<(), String> {
+    table::validate(&record.id, record.lat, record.long, record.device_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_localities_txt() {
+        let registry = LocalityRegistry::from_text(&std::fs::read_to_string("localities.txt").unwrap()).unwrap();
+        assert_eq!(registry.len(), 580);
+        let id = registry.locality("ZWL003417").unwrap();
+        assert_eq!(registry.name(id), Some("Bhopal People's Mall, Bhopal"));
+        assert_eq!(registry.device_type(id), Some(2));
+        assert_eq!(registry, LocalityRegistry::builtin());
+    }
+
+    #[test]
+    fn test_csv_json_and_merge() {
+        let csv = "id,name,lat,long,device_type\nZWL999001,\"Pune Test, Pune\",18.5,73.8,1\n";
+        let json = r#"[{"id": "ZWL999001", "name": "Pune Renamed", "lat": 18.5, "long": 73.8, "device_type": 2}]"#;
+        let mut registry = LocalityRegistry::builtin();
+        let before = registry.len();
+        registry.merge(LocalityRegistry::from_csv(csv).unwrap());
+        assert_eq!(registry.len(), before + 1);
+        let id = registry.locality("ZWL999001").unwrap();
+        assert_eq!(registry.city(id), Some("Pune"));
+        registry.merge(LocalityRegistry::from_json(json).unwrap());
+        assert_eq!(registry.name(id), Some("Pune Renamed"));
+        assert_eq!(registry.locality("ZWL005764").unwrap(), LocalityId::ZWL005764);
+        assert!(registry.locality("ZWL000000").is_err());
+    }
+
+    #[test]
+    fn test_runtime_ids() {
+        let csv = "id,name,lat,long,device_type\nZWL999002,Surat Runtime Test,21.17,72.83,1\n";
+        let registry = LocalityRegistry::from_csv(csv).unwrap();
+        let id = registry.locality("ZWL999002").unwrap();
+        // Loading alone leaves the id unknown outside the registry
+        assert!(LocalityId::from_str("ZWL999002").is_err());
+        assert_eq!(id.locality_name(), None);
+        registry.install();
+        assert_eq!(LocalityId::from_str("ZWL999002").unwrap(), id);
+        assert_eq!(id.locality_name(), Some("Surat Runtime Test"));
+        assert_eq!(id.city(), Some("Surat"));
+        assert_eq!((id.locality_lat_long(), id.device_type()), (Some((21.17, 72.83)), Some(1)));
+        assert_eq!(id.to_string(), "ZWL999002 Surat Runtime Test");
+        // Built-in lists are unchanged
+        assert!(!LocalityId::in_city("Surat").any(|surat| surat == id));
+
+        // Loading the same table again reuses the interned strings
+        let name = id.locality_name().unwrap();
+        let registry = LocalityRegistry::from_csv(csv).unwrap();
+        registry.install();
+        let again = registry.locality("ZWL999002").unwrap();
+        assert!(std::ptr::eq(id.0, again.0));
+        assert!(std::ptr::eq(name, again.locality_name().unwrap()));
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(matches!(LocalityRegistry::from_text("\nDelhi ZWL12 28.5 77.2 1 - Automated weather system"),
+            Err(RegistryError::Malformed { line: 2, .. })));
+        assert!(matches!(LocalityRegistry::from_text("Delhi ZWL123456 north 77.2 1 - Automated weather system"),
+            Err(RegistryError::Malformed { line: 1, .. })));
+        assert!(matches!(LocalityRegistry::from_json(r#"[{"id": "ZWL123456", "name": "London", "lat": 51.5, "long": -0.1, "device_type": 1}]"#),
+            Err(RegistryError::Malformed { line: 1, .. })));
+        // A bad record rejects the whole table, and none of it becomes known even after valid lines
+        let partly = "id,name,lat,long,device_type\nZWL999003,Goa Partial,15.4,73.8,1\nZWL999004,Nowhere,0,0,1\n";
+        assert!(matches!(LocalityRegistry::from_csv(partly), Err(RegistryError::Malformed { line: 2, .. })));
+        assert!(LocalityId::from_str("ZWL999003").is_err());
+    }
+}
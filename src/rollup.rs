@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::history::{HistoryError, HistoryStore};
+use crate::{LocalityId, Metric, Reading};
+
+/// Offset of Indian Standard Time from UTC in seconds, where WeatherUnion devices reset their rain counters
+pub const IST_OFFSET: i32 = 5 * 3600 + 1800;
+
+/// A drop in `rain_accumulation` smaller than this is rounding noise rather than a counter reset
+const RESET_TOLERANCE: f64 = 0.05;
+
+/// Compass sectors used to find the dominant wind direction
+const SECTORS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    Hour, Day
+}
+
+impl Period {
+
+    /// Name used in CSV and serde output
+    pub fn name(&self) -> &'static str {
+        match self {
+            Period::Hour => "hour",
+            Period::Day => "day",
+        }
+    }
+
+    /// Length of the period in seconds
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Period::Hour => 3600,
+            Period::Day => 86400,
+        }
+    }
+}
+
+impl Serialize for Period {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// Smallest, largest and mean value of a metric within a rollup
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Range {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl Range {
+    fn new(values: &[f64]) -> Option<Range> {
+        if values.is_empty() {
+            return None
+        }
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        Some(Range { min, max, mean: values.iter().sum::<f64>() / values.len() as f64 })
+    }
+}
+
+/// Summary of the readings of one locality within one hour or day.\
+/// Metrics the device does not report are None.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Rollup {
+    #[serde(serialize_with = "unix_seconds")]
+    pub start: SystemTime,
+    pub period: Period,
+    /// Number of readings in the period, periods without readings have no rollup
+    pub readings: usize,
+    pub temperature: Option<Range>,
+    pub humidity: Option<Range>,
+    pub wind_speed: Option<Range>,
+    /// Centre of the 22.5° compass sector the wind blew from most often while not calm
+    pub wind_direction: Option<f64>,
+    /// Rain in millimetres that fell in the period, None when no reading in it has `rain_accumulation`
+    pub rain: Option<f64>,
+    pub rain_intensity_max: Option<f64>,
+}
+
+fn unix_seconds<S: serde::Serializer>(at: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0))
+}
+
+/// Builds [`Rollup`]s from a series of readings, with periods aligned to local time.\
+/// `rain_accumulation` counts from local midnight, so rain is summed from the increase between consecutive
+/// readings, and the first reading of a day, or one below the reading before it, counts from zero.
+/// # Example usage
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use weather_union::history::MemoryHistory;
+/// use weather_union::rollup::{self, Period, Rollups};
+/// use weather_union::LocalityId;
+/// let history = MemoryHistory::new();
+/// let now = SystemTime::now();
+/// let week = Rollups::new(Period::Day)
+///     .from_store(&history, LocalityId::ZWL005764, now - Duration::from_secs(7 * 86400), now)
+///     .unwrap();
+/// for day in &week {
+///     println!("{:?} rain {:?} mm, max {:?}", day.start, day.rain, day.temperature.map(|t| t.max));
+/// }
+/// println!("{}", rollup::to_csv(week.iter().map(|day| (LocalityId::ZWL005764, day))));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rollups {
+    period: Period,
+    utc_offset: i32,
+}
+
+#[derive(Default)]
+struct Bucket {
+    readings: usize,
+    temperature: Vec<f64>,
+    humidity: Vec<f64>,
+    wind_speed: Vec<f64>,
+    sectors: [usize; SECTORS],
+    rain: Option<f64>,
+    rain_intensity_max: Option<f64>,
+}
+
+impl Rollups {
+
+    /// Periods aligned to Indian Standard Time
+    pub fn new(period: Period) -> Rollups {
+        Rollups { period, utc_offset: IST_OFFSET }
+    }
+
+    /// Align periods and rain counter resets to another UTC offset, in seconds
+    pub fn utc_offset(mut self, seconds: i32) -> Rollups {
+        self.utc_offset = seconds;
+        self
+    }
+
+    fn local_seconds(&self, at: SystemTime) -> i64 {
+        let unix = match at.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+        unix + self.utc_offset as i64
+    }
+
+    fn start_of(&self, local: i64, period: i64) -> i64 {
+        local.div_euclid(period) * period
+    }
+
+    /// Rain that fell up to a reading since the one before it, or since midnight if it is the first of its day
+    fn rain_since(&self, previous: Option<(SystemTime, f64)>, current: (SystemTime, f64)) -> f64 {
+        let day = |at| self.start_of(self.local_seconds(at), Period::Day.seconds());
+        match previous {
+            Some(previous) if day(previous.0) == day(current.0) && current.1 >= previous.1 - RESET_TOLERANCE => {
+                (current.1 - previous.1).max(0.0)
+            }
+            _ => current.1,
+        }
+    }
+
+    /// Roll up a series of readings of one locality, which need not be sorted.\
+    /// A reading missing `rain_accumulation` is skipped for rain, the next reading counts from the one before it.
+    pub fn compute(&self, readings: &[(SystemTime, Reading)]) -> Vec<Rollup> {
+        let mut sorted = readings.to_vec();
+        sorted.sort_by_key(|(at, _)| *at);
+        let period = self.period.seconds();
+        let mut buckets: BTreeMap<i64, Bucket> = BTreeMap::new();
+        let mut previous_rain: Option<(SystemTime, f64)> = None;
+        for (at, reading) in &sorted {
+            let bucket = buckets.entry(self.start_of(self.local_seconds(*at), period)).or_default();
+            bucket.readings += 1;
+            bucket.temperature.extend(reading.metric(Metric::Temperature));
+            bucket.humidity.extend(reading.metric(Metric::Humidity));
+            bucket.wind_speed.extend(reading.metric(Metric::WindSpeed));
+            if let (Some(speed), Some(direction)) = (reading.metric(Metric::WindSpeed), reading.metric(Metric::WindDirection)) {
+                if speed > 0.0 {
+                    let width = 360.0 / SECTORS as f64;
+                    bucket.sectors[((direction.rem_euclid(360.0) + width / 2.0) / width) as usize % SECTORS] += 1;
+                }
+            }
+            if let Some(intensity) = reading.metric(Metric::RainIntensity) {
+                bucket.rain_intensity_max = Some(bucket.rain_intensity_max.map_or(intensity, |max| max.max(intensity)));
+            }
+            // None for a missing value, which must not become the baseline or look like a reset
+            if let Some(accumulation) = reading.metric(Metric::RainAccumulation) {
+                *bucket.rain.get_or_insert(0.0) += self.rain_since(previous_rain, (*at, accumulation));
+                previous_rain = Some((*at, accumulation));
+            }
+        }
+        buckets.into_iter().map(|(local, bucket)| {
+            let unix = local - self.utc_offset as i64;
+            let start = if unix >= 0 {
+                UNIX_EPOCH + Duration::from_secs(unix as u64)
+            } else {
+                UNIX_EPOCH - Duration::from_secs(unix.unsigned_abs())
+            };
+            // Most frequent sector, the first one on ties
+            let dominant = (0..SECTORS).filter(|s| bucket.sectors[*s] > 0)
+                .max_by_key(|s| (bucket.sectors[*s], std::cmp::Reverse(*s)));
+            Rollup {
+                start,
+                period: self.period,
+                readings: bucket.readings,
+                temperature: Range::new(&bucket.temperature),
+                humidity: Range::new(&bucket.humidity),
+                wind_speed: Range::new(&bucket.wind_speed),
+                wind_direction: dominant.map(|s| s as f64 * 360.0 / SECTORS as f64),
+                rain: bucket.rain,
+                rain_intensity_max: bucket.rain_intensity_max,
+            }
+        }).collect()
+    }
+
+    /// Roll up the stored readings of a locality taken in `[since, until]`.\
+    /// The reading before `since` is not read, so if `since` falls within a day the first reading counts
+    /// the rain since midnight, including any that fell before `since`.
+    pub fn from_store<S: HistoryStore + ?Sized>(&self, store: &S, id: LocalityId, since: SystemTime, until: SystemTime)
+        -> Result<Vec<Rollup>, HistoryError> {
+        Ok(self.compute(&store.range(id, since, until)?))
+    }
+}
+
+/// CSV with a header line and a line per rollup, missing values are left empty.
+pub fn to_csv<'a>(rollups: impl IntoIterator<Item = (LocalityId, &'a Rollup)>) -> String {
+    let mut csv = String::from("locality,start,period,readings");
+    for metric in ["temperature", "humidity", "wind_speed"] {
+        let _ = write!(csv, ",{metric}_min,{metric}_max,{metric}_mean");
+    }
+    csv.push_str(",wind_direction,rain,rain_intensity_max\n");
+    let value = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+    for (id, rollup) in rollups {
+        let start = rollup.start.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let _ = write!(csv, "{},{},{},{}", id.0, start, rollup.period.name(), rollup.readings);
+        for range in [rollup.temperature, rollup.humidity, rollup.wind_speed] {
+            let _ = write!(csv, ",{},{},{}", value(range.map(|r| r.min)), value(range.map(|r| r.max)), value(range.map(|r| r.mean)));
+        }
+        let _ = writeln!(csv, ",{},{},{}", value(rollup.wind_direction), value(rollup.rain), value(rollup.rain_intensity_max));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aws, rgs, LocalityWeatherData};
+
+    const HOUR: u64 = 3600;
+    /// 2024-01-01T00:00:00Z
+    const START: u64 = 1_704_067_200;
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(START + seconds)
+    }
+
+    #[test]
+    fn test_hourly_with_gaps() {
+        let series = [
+            (at(HOUR * 3 + 600), LocalityWeatherData { wind_speed: 1.0, wind_direction: 350.0, ..aws(24.0) }.into()),
+            (at(600), LocalityWeatherData { wind_speed: 1.0, wind_direction: 10.0, ..aws(20.0) }.into()),
+            (at(1800), LocalityWeatherData { wind_direction: 5.0, ..aws(22.0) }.into()),
+            (at(3000), LocalityWeatherData { wind_speed: 0.0, wind_direction: 180.0, ..aws(21.0) }.into()),
+            // Nothing in hours 1 and 2
+        ];
+        let hours = Rollups::new(Period::Hour).utc_offset(0).compute(&series);
+        assert_eq!(hours.len(), 2);
+        assert_eq!(hours[0].start, at(0));
+        assert_eq!(hours[0].readings, 3);
+        assert_eq!(hours[0].temperature, Some(Range { min: 20.0, max: 22.0, mean: 21.0 }));
+        // The calm reading does not count towards the direction
+        assert_eq!(hours[0].wind_direction, Some(0.0));
+        assert_eq!(hours[1].start, at(HOUR * 3));
+        assert_eq!(hours[1].wind_direction, Some(0.0));
+        assert_eq!(hours[1].rain, Some(0.0));
+        assert!(Rollups::new(Period::Hour).compute(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_rain_resets() {
+        let series = [
+            (at(HOUR * 20), rgs(0.5, 2.0).into()),
+            (at(HOUR * 22), rgs(2.0, 5.0).into()),
+            // The counter restarts at midnight, 1.5 mm fell after it
+            (at(HOUR * 25), rgs(0.0, 1.5).into()),
+            // Rounding noise is not a reset
+            (at(HOUR * 26), rgs(0.0, 1.48).into()),
+            // A device restart mid-day
+            (at(HOUR * 27), rgs(0.0, 0.5).into()),
+            // A gap of a day, still a new count on the later day
+            (at(HOUR * 60), rgs(1.0, 4.0).into()),
+        ];
+        let days = Rollups::new(Period::Day).utc_offset(0).compute(&series);
+        assert_eq!(days.len(), 3);
+        // The first reading counts from midnight
+        assert_eq!(days[0].rain, Some(5.0));
+        assert_eq!(days[0].rain_intensity_max, Some(2.0));
+        assert_eq!(days[1].rain, Some(2.0));
+        assert_eq!(days[2].rain, Some(4.0));
+        assert_eq!(days[0].temperature, None);
+        assert_eq!(days[0].wind_direction, None);
+
+        // An IST day starts at 18:30 UTC, so the first five readings share one
+        let ist = Rollups::new(Period::Day).compute(&series);
+        assert_eq!(ist[0].start, at(HOUR * 18 + 1800));
+        assert_eq!(ist[0].readings, 5);
+        assert_eq!(ist[0].rain, Some(5.0 + 1.5 + 0.5));
+        assert_eq!(ist[1].rain, Some(4.0));
+    }
+
+    #[test]
+    fn test_missing_accumulation() {
+        let body = r#"{"status":"200","message":"","device_type":2,"locality_weather_data":{"rain_intensity":0.0,"rain_accumulation":null}}"#;
+        let gap = crate::process_payload(http::StatusCode::OK, body).unwrap();
+        let series = [(at(HOUR), rgs(0.5, 5.0).into()), (at(HOUR * 2), gap), (at(HOUR * 3), rgs(0.5, 5.2).into())];
+        let days = Rollups::new(Period::Day).utc_offset(0).compute(&series);
+        assert_eq!(days[0].readings, 3);
+        assert!((days[0].rain.unwrap() - 5.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_exports() {
+        let series = [(at(0), LocalityWeatherData { wind_speed: 1.0, ..aws(20.0) }.into()), (at(600), rgs(1.0, 0.5).into())];
+        let hours = Rollups::new(Period::Hour).utc_offset(0).compute(&series);
+        let csv = to_csv(hours.iter().map(|hour| (LocalityId::ZWL005764, hour)));
+        let mut lines = csv.lines();
+        assert_eq!(lines.next().unwrap().split(',').count(), 16);
+        assert_eq!(lines.next().unwrap(), "ZWL005764,1704067200,hour,2,20,20,20,60,60,60,1,1,1,90,0.5,1");
+        let json = serde_json::to_value(&hours[0]).unwrap();
+        assert_eq!(json["start"], 1704067200);
+        assert_eq!(json["period"], "hour");
+        assert_eq!(json["temperature"]["mean"], 20.0);
+    }
+}
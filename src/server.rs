@@ -0,0 +1,628 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use futures_util::{stream, StreamExt};
+use schemars::generate::{Contract, SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::aggregate::{Aggregate, Summary};
+use crate::history::{HistoryError, HistoryStore, MemoryHistory, Retention};
+use crate::spatial::SpatialIndex;
+use crate::{LocalityId, Metric, Reading, WeatherUnion, CITIES};
+
+/// Most localities `/nearest` returns
+const MAX_NEAREST: usize = 50;
+/// Upstream requests in flight while summarising a city
+const CONCURRENT_FETCHES: usize = 8;
+
+/// OpenAPI 3.0 schemas of the response bodies, derived from their types
+fn schemas() -> SchemaGenerator {
+    SchemaSettings::openapi3().with(|settings| settings.contract = Contract::Serialize).into_generator()
+}
+
+fn schema<T: JsonSchema>(schemas: &mut SchemaGenerator) -> Value {
+    schemas.subschema_for::<T>().to_value()
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "Locality")]
+struct LocalityInfo {
+    #[schemars(example = &"ZWL005764")]
+    id: &'static str,
+    name: Option<&'static str>,
+    city: Option<&'static str>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    /// 1 for an automated weather system, 2 for a rain gauge
+    device_type: Option<u8>,
+}
+
+impl From<LocalityId> for LocalityInfo {
+    fn from(id: LocalityId) -> LocalityInfo {
+        let lat_long = id.locality_lat_long();
+        LocalityInfo {
+            id: id.0,
+            name: crate::area_name(id.0),
+            city: id.city(),
+            latitude: lat_long.map(|(lat, _)| lat),
+            longitude: lat_long.map(|(_, long)| long),
+            device_type: id.device_type(),
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+struct NearestLocality {
+    locality: LocalityInfo,
+    distance_km: f64,
+}
+
+/// A reading with null for the metrics its device does not report or left out
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "Reading")]
+struct ReadingBody {
+    device: u8,
+    temperature: Option<f64>,
+    humidity: Option<f64>,
+    wind_speed: Option<f64>,
+    wind_direction: Option<f64>,
+    rain_intensity: Option<f64>,
+    rain_accumulation: Option<f64>,
+}
+
+impl From<&Reading> for ReadingBody {
+    fn from(reading: &Reading) -> ReadingBody {
+        ReadingBody {
+            device: reading.data.device,
+            temperature: reading.metric(Metric::Temperature),
+            humidity: reading.metric(Metric::Humidity),
+            wind_speed: reading.metric(Metric::WindSpeed),
+            wind_direction: reading.metric(Metric::WindDirection),
+            rain_intensity: reading.metric(Metric::RainIntensity),
+            rain_accumulation: reading.metric(Metric::RainAccumulation),
+        }
+    }
+}
+
+/// Wind direction is averaged on the circle, and its median, min and max are taken within 180° of that mean
+#[derive(Serialize, JsonSchema)]
+struct MetricSummary {
+    count: usize,
+    mean: f64,
+    median: f64,
+    min: f64,
+    max: f64,
+}
+
+impl From<&Summary> for MetricSummary {
+    fn from(summary: &Summary) -> MetricSummary {
+        MetricSummary {
+            count: summary.count(), mean: summary.mean(), median: summary.median(), min: summary.min(), max: summary.max(),
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+struct CitySummary {
+    city: &'static str,
+    /// Known localities of the city
+    localities: usize,
+    /// Localities with a reading in the summary
+    reporting: usize,
+    metrics: BTreeMap<&'static str, MetricSummary>,
+    /// Failed fetches by WeatherResponseError variant
+    errors: BTreeMap<&'static str, usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct HistoryEntry {
+    /// Unix seconds
+    time: u64,
+    reading: ReadingBody,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct LocalityHistory {
+    id: &'static str,
+    readings: Vec<HistoryEntry>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "Error")]
+struct ApiError {
+    error: String,
+}
+
+fn operation(summary: &str, parameters: Value, ok: Value, error: &Value) -> Value {
+    json!({
+        "summary": summary,
+        "parameters": parameters,
+        "responses": {
+            "200": { "description": "OK", "content": { "application/json": { "schema": ok } } },
+            "4XX": { "description": "Invalid request", "content": { "application/json": { "schema": error } } },
+        },
+    })
+}
+
+/// An operation that reads or writes the history store, which answers 500 when the store fails
+fn store_operation(summary: &str, parameters: Value, ok: Value, error: &Value) -> Value {
+    let mut operation = operation(summary, parameters, ok, error);
+    operation["responses"]["500"] = json!({
+        "description": "History store failed", "content": { "application/json": { "schema": error } },
+    });
+    operation
+}
+
+fn parameter(name: &str, place: &str, kind: &str, required: bool, description: &str) -> Value {
+    json!({ "name": name, "in": place, "required": required, "description": description, "schema": { "type": kind } })
+}
+
+/// OpenAPI 3.0 document of the [`ApiServer`] routes, served on `/openapi.json`
+/// # Example
+/// ```
+///  use weather_union::server::openapi;
+///  let document = openapi();
+///  assert!(document["paths"]["/city/{name}/summary"]["get"].is_object());
+/// ```
+pub fn openapi() -> Value {
+    let mut schemas = schemas();
+    let error = schema::<ApiError>(&mut schemas);
+    json!({
+        "openapi": "3.0.3",
+        "info": { "title": "WeatherUnion local API", "version": env!("CARGO_PKG_VERSION") },
+        "paths": {
+            "/localities": { "get": operation("Known localities", json!([
+                parameter("city", "query", "string", false, "Only localities of this city, ignoring case"),
+                parameter("device", "query", "integer", false, "Only localities with this device type"),
+                parameter("q", "query", "string", false, "Words the id or name must contain"),
+            ]), schema::<Vec<LocalityInfo>>(&mut schemas), &error) },
+            "/nearest": { "get": operation("Localities closest to a point, nearest first", json!([
+                parameter("lat", "query", "number", true, "Latitude"),
+                parameter("long", "query", "number", true, "Longitude"),
+                parameter("k", "query", "integer", false, "Number of localities, 1 to 50, default 1"),
+            ]), schema::<Vec<NearestLocality>>(&mut schemas), &error) },
+            "/city/{name}/summary": { "get": store_operation("Current readings of a city aggregated per metric", json!([
+                parameter("name", "path", "string", true, "City name, ignoring case"),
+            ]), schema::<CitySummary>(&mut schemas), &error) },
+            "/locality/{id}/history": { "get": store_operation("Stored readings of a locality, oldest first", json!([
+                parameter("id", "path", "string", true, "Locality id"),
+                parameter("since", "query", "integer", false, "Unix seconds, default 0"),
+                parameter("until", "query", "integer", false, "Unix seconds, default now"),
+                parameter("limit", "query", "integer", false, "Keep only the newest readings"),
+            ]), schema::<LocalityHistory>(&mut schemas), &error) },
+        },
+        "components": { "schemas": schemas.take_definitions(true) },
+    })
+}
+
+type Store = Box<dyn HistoryStore + Send>;
+
+struct Shared {
+    client: WeatherUnion,
+    max_age: Duration,
+    /// Store calls may block on disk, handlers make them through [`with_history`]
+    history: Mutex<Store>,
+}
+
+/// JSON API over the locality tables and [`WeatherUnion`] fetches.\
+/// Readings fetched for city summaries, or passed to [`ApiServer::record`], are appended to a [`HistoryStore`]
+/// for `/locality/{id}/history` and reused by summaries until they are `max_age` old.
+/// # Example usage
+/// ```
+/// use std::time::Duration;
+/// use weather_union::server::ApiServer;
+/// use weather_union::WeatherUnion;
+/// async fn example(){
+///     let client = WeatherUnion::from_key("api_key".to_string());
+///     let listener = tokio::net::TcpListener::bind("127.0.0.1:8000").await.unwrap();
+///     ApiServer::new(client, Duration::from_secs(300)).serve(listener).await.unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ApiServer {
+    shared: Arc<Shared>,
+}
+
+type ApiResult<T> = Result<Json<T>, (StatusCode, Json<ApiError>)>;
+
+fn error(status: StatusCode, message: impl Into<String>) -> (StatusCode, Json<ApiError>) {
+    (status, Json(ApiError { error: message.into() }))
+}
+
+fn store_error(e: HistoryError) -> (StatusCode, Json<ApiError>) {
+    error(StatusCode::INTERNAL_SERVER_ERROR, format!("history store failed: {:?}", e))
+}
+
+fn unix_seconds(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl ApiServer {
+
+    /// Keep history in memory, see [`ApiServer::prune`] to bound it
+    pub fn new(client: WeatherUnion, max_age: Duration) -> ApiServer {
+        ApiServer::with_history(client, max_age, MemoryHistory::new())
+    }
+
+    pub fn with_history(client: WeatherUnion, max_age: Duration, history: impl HistoryStore + Send + 'static) -> ApiServer {
+        ApiServer {
+            shared: Arc::new(Shared { client, max_age, history: Mutex::new(Box::new(history)) }),
+        }
+    }
+
+    pub fn client(&self) -> &WeatherUnion {
+        &self.shared.client
+    }
+
+    /// Keep a reading obtained elsewhere, e.g. from [`WeatherUnion::watch`].\
+    /// The store is written from the blocking thread pool, a panic in it is resumed here.
+    pub async fn record(&self, id: LocalityId, reading: Reading, at: SystemTime) -> Result<bool, HistoryError> {
+        on_store(&self.shared, move |history| history.append(id, at, reading)).await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    }
+
+    /// Apply a retention policy to the history from the blocking thread pool, returns how many readings were dropped.
+    pub async fn prune(&self, retention: Retention) -> Result<usize, HistoryError> {
+        on_store(&self.shared, move |history| history.prune(retention, SystemTime::now())).await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/localities", get(localities))
+            .route("/nearest", get(nearest))
+            .route("/city/{name}/summary", get(city_summary))
+            .route("/locality/{id}/history", get(history))
+            .route("/openapi.json", get(|| async { Json(openapi()) }))
+            .with_state(self.shared.clone())
+    }
+
+    /// Serve until the listener fails
+    pub async fn serve(self, listener: tokio::net::TcpListener) -> std::io::Result<()> {
+        axum::serve(listener, self.router()).await
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Run `f` on the history store in the blocking thread pool, so a slow store does not stall the runtime
+async fn on_store<T: Send + 'static>(shared: &Arc<Shared>, f: impl FnOnce(&mut Store) -> Result<T, HistoryError> + Send + 'static)
+    -> Result<Result<T, HistoryError>, tokio::task::JoinError> {
+    let shared = shared.clone();
+    tokio::task::spawn_blocking(move || f(&mut lock(&shared.history))).await
+}
+
+/// [`on_store`] for handlers, a failed or panicking store is a 500
+async fn with_history<T: Send + 'static>(shared: &Arc<Shared>, f: impl FnOnce(&mut Store) -> Result<T, HistoryError> + Send + 'static)
+    -> Result<T, (StatusCode, Json<ApiError>)> {
+    on_store(shared, f)
+        .await
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, format!("history store failed: {}", e)))?
+        .map_err(store_error)
+}
+
+fn parse<T: std::str::FromStr>(query: &HashMap<String, String>, name: &str) -> Result<Option<T>, (StatusCode, Json<ApiError>)> {
+    query.get(name)
+        .map(|value| value.parse().map_err(|_| error(StatusCode::BAD_REQUEST, format!("invalid {}", name))))
+        .transpose()
+}
+
+/// A unix time in seconds, rejecting times a `SystemTime` cannot hold
+fn parse_time(query: &HashMap<String, String>, name: &str) -> Result<Option<SystemTime>, (StatusCode, Json<ApiError>)> {
+    parse(query, name)?
+        .map(|secs| UNIX_EPOCH.checked_add(Duration::from_secs(secs)).ok_or_else(|| error(StatusCode::BAD_REQUEST, format!("invalid {}", name))))
+        .transpose()
+}
+
+async fn localities(Query(query): Query<HashMap<String, String>>) -> ApiResult<Vec<LocalityInfo>> {
+    let device: Option<u8> = parse(&query, "device")?;
+    let city = query.get("city");
+    let ids = LocalityId::search(query.get("q").map(String::as_str).unwrap_or(""))
+        .filter(|id| city.is_none_or(|city| id.city().is_some_and(|c| c.eq_ignore_ascii_case(city))))
+        .filter(|id| device.is_none_or(|device| id.device_type() == Some(device)));
+    Ok(Json(ids.map(LocalityInfo::from).collect()))
+}
+
+async fn nearest(Query(query): Query<HashMap<String, String>>) -> ApiResult<Vec<NearestLocality>> {
+    let lat: Option<f64> = parse(&query, "lat")?;
+    let long: Option<f64> = parse(&query, "long")?;
+    let Some((lat, long)) = lat.zip(long).filter(|(lat, long)| lat.abs() <= 90.0 && long.abs() <= 180.0) else {
+        return Err(error(StatusCode::BAD_REQUEST, "lat and long are required"))
+    };
+    let k = parse(&query, "k")?.unwrap_or(1);
+    if !(1..=MAX_NEAREST).contains(&k) {
+        return Err(error(StatusCode::BAD_REQUEST, format!("k must be between 1 and {}", MAX_NEAREST)))
+    }
+    let found = SpatialIndex::builtin().nearest((lat, long), k).into_iter()
+        .map(|(id, distance_km)| NearestLocality { locality: id.into(), distance_km })
+        .collect();
+    Ok(Json(found))
+}
+
+async fn city_summary(State(shared): State<Arc<Shared>>, Path(name): Path<String>) -> ApiResult<CitySummary> {
+    let Some(city) = CITIES.iter().copied().find(|city| city.eq_ignore_ascii_case(&name)) else {
+        return Err(error(StatusCode::NOT_FOUND, format!("unknown city {}", name)))
+    };
+    let ids: Vec<LocalityId> = LocalityId::in_city(city).collect();
+    let now = SystemTime::now();
+    let max_age = shared.max_age;
+    let (mut readings, stale) = with_history(&shared, {
+        let ids = ids.clone();
+        move |history| {
+            let mut readings = Vec::new();
+            let mut stale = Vec::new();
+            for id in ids {
+                match history.latest(id)? {
+                    Some((at, data)) if now.duration_since(at).unwrap_or_default() < max_age => readings.push(data),
+                    _ => stale.push(id),
+                }
+            }
+            Ok((readings, stale))
+        }
+    }).await?;
+    let fetched: Vec<_> = stream::iter(stale)
+        .map(|id| {
+            let client = &shared.client;
+            async move { (id, client.locality_reading(id).await) }
+        })
+        .buffer_unordered(CONCURRENT_FETCHES)
+        .collect()
+        .await;
+    let mut errors = BTreeMap::new();
+    let mut fresh = Vec::new();
+    for (id, result) in fetched {
+        match result {
+            Ok(data) => fresh.push((id, data)),
+            Err(e) => *errors.entry(e.variant()).or_insert(0) += 1,
+        }
+    }
+    readings.extend(fresh.iter().map(|(_, data)| *data));
+    with_history(&shared, move |history| {
+        fresh.into_iter().try_for_each(|(id, data)| history.append(id, now, data).map(|_| ()))
+    }).await?;
+    let aggregate = Aggregate::new(&readings);
+    Ok(Json(CitySummary {
+        city,
+        localities: ids.len(),
+        reporting: aggregate.localities,
+        metrics: aggregate.metrics().map(|(metric, summary)| (metric.name(), summary.into())).collect(),
+        errors,
+    }))
+}
+
+async fn history(State(shared): State<Arc<Shared>>, Path(id): Path<String>,
+                 Query(query): Query<HashMap<String, String>>) -> ApiResult<LocalityHistory> {
+    let Ok(id) = LocalityId::from_str(&id) else {
+        return Err(error(StatusCode::NOT_FOUND, format!("unknown locality {}", id)))
+    };
+    let since = parse_time(&query, "since")?.unwrap_or(UNIX_EPOCH);
+    let until = parse_time(&query, "until")?.unwrap_or_else(SystemTime::now);
+    let mut readings = with_history(&shared, move |history| history.range(id, since, until)).await?;
+    if let Some(limit) = parse::<usize>(&query, "limit")? {
+        readings.drain(..readings.len().saturating_sub(limit));
+    }
+    let readings = readings.iter().map(|(at, data)| HistoryEntry { time: unix_seconds(*at), reading: data.into() }).collect();
+    Ok(Json(LocalityHistory { id: id.0, readings }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aws, rgs};
+    use crate::transport::{FakeTransport, HttpResponse};
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    const READING: &str = r#"{"status":"200","message":"","device_type":1,"locality_weather_data":{"temperature":24.5,"humidity":60.0,"wind_speed":1.5,"wind_direction":90.0,"rain_intensity":0.0,"rain_accumulation":2.0}}"#;
+
+    /// Check a serialized value against its schema, following references.\
+    /// Serde writes every field, null included, so every property of the schema must be present.
+    fn conforms(document: &Value, schema: &Value, value: &Value) {
+        if let Some(path) = schema["$ref"].as_str() {
+            let name = path.rsplit('/').next().unwrap();
+            return conforms(document, &document["components"]["schemas"][name], value)
+        }
+        if value.is_null() {
+            assert_eq!(schema["nullable"], true, "{} is not nullable", schema);
+            return
+        }
+        match schema["type"].as_str().unwrap() {
+            "object" => {
+                let object = value.as_object().unwrap();
+                for required in schema["required"].as_array().into_iter().flatten() {
+                    assert!(object.contains_key(required.as_str().unwrap()), "missing {}", required);
+                }
+                for property in schema["properties"].as_object().into_iter().flat_map(|properties| properties.keys()) {
+                    assert!(object.contains_key(property), "{} is in the schema but not serialized", property);
+                }
+                for (key, field) in object {
+                    let field_schema = match schema["properties"].get(key) {
+                        Some(property) => property,
+                        None => &schema["additionalProperties"],
+                    };
+                    assert!(!field_schema.is_null(), "{} is not in the schema", key);
+                    conforms(document, field_schema, field);
+                }
+            }
+            "array" => value.as_array().unwrap().iter().for_each(|item| conforms(document, &schema["items"], item)),
+            "string" => assert!(value.is_string()),
+            "integer" => assert!(value.is_u64()),
+            "number" => assert!(value.is_number()),
+            other => panic!("unexpected type {}", other),
+        }
+    }
+
+    #[test]
+    fn test_openapi_matches_responses() {
+        let document = openapi();
+        let data = Reading::from(rgs(0.5, 3.0));
+        let summary = Summary::new(Metric::RainIntensity, [0.5, 1.0]).unwrap();
+        let city = CitySummary {
+            city: "Surat", localities: 3, reporting: 2,
+            metrics: BTreeMap::from([("rain_intensity", (&summary).into())]),
+            errors: BTreeMap::from([("ApiKeyLimitExhausted", 1)]),
+        };
+        let history = LocalityHistory { id: "ZWL008436", readings: vec![HistoryEntry { time: 1_700_000_000, reading: (&data).into() }] };
+        let nearest = NearestLocality { locality: LocalityId::ZWL005764.into(), distance_km: 1.5 };
+        let samples = [
+            (LocalityInfo::schema_name(), serde_json::to_value(LocalityInfo::from(LocalityId::ZWL008436)).unwrap()),
+            (NearestLocality::schema_name(), serde_json::to_value(&nearest).unwrap()),
+            (ReadingBody::schema_name(), serde_json::to_value(ReadingBody::from(&data)).unwrap()),
+            (MetricSummary::schema_name(), serde_json::to_value(MetricSummary::from(&summary)).unwrap()),
+            (CitySummary::schema_name(), serde_json::to_value(&city).unwrap()),
+            (HistoryEntry::schema_name(), serde_json::to_value(&history.readings[0]).unwrap()),
+            (LocalityHistory::schema_name(), serde_json::to_value(&history).unwrap()),
+            (ApiError::schema_name(), serde_json::to_value(ApiError { error: "unknown city".into() }).unwrap()),
+        ];
+        for (name, value) in &samples {
+            conforms(&document, &document["components"]["schemas"][name.as_ref()], value);
+        }
+        // Every schema in the document has a sample
+        let names: Vec<&str> = document["components"]["schemas"].as_object().unwrap().keys().map(String::as_str).collect();
+        let mut sampled: Vec<&str> = samples.iter().map(|(name, _)| name.as_ref()).collect();
+        sampled.sort();
+        assert_eq!(names, sampled);
+        // and a reading has a property per metric
+        let properties = document["components"]["schemas"]["Reading"]["properties"].as_object().unwrap();
+        assert!(Metric::ALL.iter().all(|metric| properties.contains_key(metric.name())));
+        assert!(serde_json::to_value(&history).unwrap()["readings"][0]["reading"]["temperature"].is_null());
+    }
+
+    #[test]
+    fn test_routes() {
+        aw!(async {
+            let city = CITIES.iter().copied().min_by_key(|city| LocalityId::in_city(city).count()).unwrap();
+            let count = LocalityId::in_city(city).count();
+            let fake = Arc::new(FakeTransport::new());
+            for _ in 0..count {
+                fake.push(HttpResponse::new(200, READING));
+            }
+            let client = WeatherUnion::from_key("api_key".to_string()).with_transport(fake.clone());
+            let server = ApiServer::new(client, Duration::from_secs(300));
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            tokio::spawn(server.clone().serve(listener));
+            let http = reqwest::Client::new();
+            let document = openapi();
+            // Every response is checked against the schema its route declares for the status
+            let get = |path: &str| {
+                let request = http.get(format!("{}{}", url, path));
+                let (route, path) = (route(path), path.to_string());
+                let document = &document;
+                async move {
+                    let response = request.send().await.unwrap();
+                    let status = response.status().as_u16();
+                    let body = serde_json::from_str::<Value>(&response.text().await.unwrap()).unwrap();
+                    if route != "/openapi.json" {
+                        let responses = &document["paths"][route]["get"]["responses"];
+                        let declared = match status {
+                            200 | 500 => &responses[status.to_string()],
+                            400..=499 => &responses["4XX"],
+                            _ => &Value::Null,
+                        };
+                        assert!(declared.is_object(), "{} answered {}, which is not documented", path, status);
+                        conforms(document, &declared["content"]["application/json"]["schema"], &body);
+                    }
+                    (status, body)
+                }
+            };
+
+            let (status, bengaluru) = get("/localities?city=bengaluru&device=1").await;
+            assert_eq!(status, 200);
+            let bengaluru = bengaluru.as_array().unwrap();
+            assert!(!bengaluru.is_empty() && bengaluru.iter().all(|l| l["city"] == "Bengaluru" && l["device_type"] == 1));
+            assert_eq!(get("/localities?q=sarita%20vihar").await.1[0]["id"], "ZWL005764");
+            assert_eq!(get("/localities?device=aws").await.0, 400);
+
+            let (_, nearest) = get("/nearest?lat=28.531759&long=77.293973&k=3").await;
+            assert_eq!(nearest.as_array().unwrap().len(), 3);
+            assert_eq!(nearest[0]["locality"]["id"], "ZWL005764");
+            assert_eq!(get("/nearest?lat=28.5").await.0, 400);
+            assert_eq!(get("/nearest?lat=28.5&long=77.2&k=0").await.0, 400);
+
+            let (status, summary) = get(&format!("/city/{}/summary", city.to_uppercase())).await;
+            assert_eq!(status, 200);
+            assert_eq!(summary["city"], city);
+            assert_eq!(summary["reporting"], count);
+            assert_eq!(summary["metrics"]["temperature"]["mean"], 24.5);
+            // Fresh readings are reused
+            assert_eq!(get(&format!("/city/{}/summary", city)).await.1["reporting"], count);
+            assert_eq!(fake.requests().len(), count);
+            assert_eq!(get("/city/Atlantis/summary").await.0, 404);
+
+            let id = LocalityId::in_city(city).next().unwrap();
+            server.record(id, aws(20.0).into(), UNIX_EPOCH + Duration::from_secs(1000)).await.unwrap();
+            let (_, history) = get(&format!("/locality/{}/history", id.0)).await;
+            assert_eq!(history["readings"].as_array().unwrap().len(), 2);
+            assert_eq!(history["readings"][0]["time"], 1000);
+            assert_eq!(get(&format!("/locality/{}/history?limit=1", id.0)).await.1["readings"][0]["reading"]["temperature"], 24.5);
+            assert_eq!(get(&format!("/locality/{}/history?until=2000", id.0)).await.1["readings"].as_array().unwrap().len(), 1);
+            assert_eq!(get("/locality/ZWL000000/history").await.0, 404);
+            assert_eq!(get(&format!("/locality/{}/history?until={}", id.0, u64::MAX)).await.0, 400);
+            assert_eq!(server.prune(Retention::new().max_readings(1)).await.unwrap(), 1);
+            assert_eq!(get(&format!("/locality/{}/history", id.0)).await.1["readings"].as_array().unwrap().len(), 1);
+
+            let (_, document) = get("/openapi.json").await;
+            assert_eq!(document["openapi"], "3.0.3");
+        });
+    }
+
+    /// Store failing every call
+    struct Broken;
+
+    impl HistoryStore for Broken {
+        fn append(&mut self, _: LocalityId, at: SystemTime, _: Reading) -> Result<bool, HistoryError> {
+            Err(HistoryError::InvalidTime(at))
+        }
+        fn range(&self, _: LocalityId, since: SystemTime, _: SystemTime) -> Result<Vec<(SystemTime, Reading)>, HistoryError> {
+            Err(HistoryError::InvalidTime(since))
+        }
+        fn latest(&self, _: LocalityId) -> Result<Option<(SystemTime, Reading)>, HistoryError> {
+            Err(HistoryError::InvalidTime(UNIX_EPOCH))
+        }
+        fn prune(&mut self, _: Retention, now: SystemTime) -> Result<usize, HistoryError> {
+            Err(HistoryError::InvalidTime(now))
+        }
+    }
+
+    #[test]
+    fn test_store_failure() {
+        let server = ApiServer::with_history(WeatherUnion::from_key("api_key".to_string()), Duration::from_secs(300), Broken);
+        let document = openapi();
+        for (route, response) in [
+            ("/locality/{id}/history", aw!(history(State(server.shared.clone()), Path("ZWL005764".into()), Query(HashMap::new()))).err()),
+            ("/city/{name}/summary", aw!(city_summary(State(server.shared.clone()), Path("Surat".into()))).err()),
+        ] {
+            let (status, Json(body)) = response.unwrap();
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+            let declared = &document["paths"][route]["get"]["responses"]["500"]["content"]["application/json"]["schema"];
+            conforms(&document, declared, &serde_json::to_value(body).unwrap());
+        }
+    }
+
+    /// The OpenAPI path of a request path
+    fn route(path: &str) -> &'static str {
+        let path = path.split('?').next().unwrap();
+        match path.split('/').collect::<Vec<_>>()[..] {
+            ["", "locality", _, "history"] => "/locality/{id}/history",
+            ["", "city", _, "summary"] => "/city/{name}/summary",
+            ["", "localities"] => "/localities",
+            ["", "nearest"] => "/nearest",
+            ["", "openapi.json"] => "/openapi.json",
+            _ => panic!("no route for {}", path),
+        }
+    }
+}
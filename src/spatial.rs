@@ -0,0 +1,234 @@
+use std::cmp::Ordering;
+use std::sync::OnceLock;
+
+use crate::geo::{haversine_km, EARTH_RADIUS_KM};
+use crate::heatmap::BoundingBox;
+use crate::LocalityId;
+
+#[derive(Clone, Copy, Debug)]
+struct Point {
+    id: LocalityId,
+    lat_long: (f64, f64),
+    /// Position on the unit sphere, chord length between two of these grows with great circle distance
+    xyz: [f64; 3],
+}
+
+fn unit_vector((lat, long): (f64, f64)) -> [f64; 3] {
+    let (lat, long) = (lat.to_radians(), long.to_radians());
+    [lat.cos() * long.cos(), lat.cos() * long.sin(), lat.sin()]
+}
+
+fn chord(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// Chord length between points `km` apart on the surface
+fn chord_for_km(km: f64) -> f64 {
+    2.0 * (km.min(std::f64::consts::PI * EARTH_RADIUS_KM) / (2.0 * EARTH_RADIUS_KM)).sin()
+}
+
+/// Nearest station queries over locality coordinates.\
+/// A k-d tree over positions on the unit sphere answers nearest and radius queries,
+/// a latitude sorted copy answers bounding box queries.
+/// # Example usage
+/// ```
+/// use weather_union::spatial::SpatialIndex;
+/// let index = SpatialIndex::builtin();
+/// let (id, km) = index.nearest((12.936787, 77.556079), 1)[0];
+/// println!("{} is {:.1} km away", id, km);
+/// ```
+#[derive(Clone, Debug)]
+pub struct SpatialIndex {
+    tree: Vec<Point>,
+    by_lat: Vec<Point>,
+}
+
+impl SpatialIndex {
+
+    /// Index the given localities, those without coordinates are skipped.
+    pub fn new(ids: impl IntoIterator<Item = LocalityId>) -> SpatialIndex {
+        let mut tree: Vec<Point> = ids.into_iter()
+            .filter_map(|id| {
+                let lat_long = id.locality_lat_long()?;
+                Some(Point { id, lat_long, xyz: unit_vector(lat_long) })
+            })
+            .collect();
+        let mut by_lat = tree.clone();
+        by_lat.sort_by(|a, b| a.lat_long.0.total_cmp(&b.lat_long.0));
+        build(&mut tree, 0);
+        SpatialIndex { tree, by_lat }
+    }
+
+    /// Index of every built-in locality, built on first use
+    pub fn builtin() -> &'static SpatialIndex {
+        static BUILTIN: OnceLock<SpatialIndex> = OnceLock::new();
+        BUILTIN.get_or_init(|| SpatialIndex::new(LocalityId::ALL.iter().copied()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// The `k` localities closest to (lat, long) with their distance in km, nearest first.
+    pub fn nearest(&self, point: (f64, f64), k: usize) -> Vec<(LocalityId, f64)> {
+        let query = unit_vector(point);
+        let mut best: Vec<(f64, Point)> = Vec::with_capacity(k + 1);
+        if k > 0 {
+            nearest(&self.tree, 0, &query, k, &mut best);
+        }
+        self.finish(point, best.into_iter().map(|(_, p)| p))
+    }
+
+    /// Localities within `km` of (lat, long) with their distance, nearest first.
+    pub fn within_radius(&self, point: (f64, f64), km: f64) -> Vec<(LocalityId, f64)> {
+        let query = unit_vector(point);
+        let mut found = Vec::new();
+        within(&self.tree, 0, &query, chord_for_km(km), &mut found);
+        let mut found = self.finish(point, found);
+        // The chord bound is exact up to rounding, trim anything it let through
+        found.retain(|(_, distance)| *distance <= km);
+        found
+    }
+
+    /// Localities inside a lat/long box, ordered by latitude.
+    pub fn within_bbox(&self, bbox: BoundingBox) -> Vec<LocalityId> {
+        let start = self.by_lat.partition_point(|p| p.lat_long.0 < bbox.south);
+        self.by_lat[start..].iter()
+            .take_while(|p| p.lat_long.0 <= bbox.north)
+            .filter(|p| bbox.contains(p.lat_long))
+            .map(|p| p.id)
+            .collect()
+    }
+
+    fn finish(&self, point: (f64, f64), found: impl IntoIterator<Item = Point>) -> Vec<(LocalityId, f64)> {
+        let mut found: Vec<(LocalityId, f64)> = found.into_iter().map(|p| (p.id, haversine_km(point, p.lat_long))).collect();
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found
+    }
+}
+
+/// Arrange `points` into an implicit k-d tree, the median of each slice is its root.
+fn build(points: &mut [Point], depth: usize) {
+    if points.len() <= 1 {
+        return
+    }
+    let axis = depth % 3;
+    let middle = points.len() / 2;
+    points.select_nth_unstable_by(middle, |a, b| a.xyz[axis].partial_cmp(&b.xyz[axis]).unwrap_or(Ordering::Equal));
+    let (left, right) = points.split_at_mut(middle);
+    build(left, depth + 1);
+    build(&mut right[1..], depth + 1);
+}
+
+fn nearest(points: &[Point], depth: usize, query: &[f64; 3], k: usize, best: &mut Vec<(f64, Point)>) {
+    if points.is_empty() {
+        return
+    }
+    let axis = depth % 3;
+    let middle = points.len() / 2;
+    let root = points[middle];
+    let distance = chord(&root.xyz, query);
+    if best.len() < k || distance < best[best.len() - 1].0 {
+        let at = best.partition_point(|(d, _)| *d <= distance);
+        best.insert(at, (distance, root));
+        best.truncate(k);
+    }
+    let offset = query[axis] - root.xyz[axis];
+    let (near, far) = if offset < 0.0 { (&points[..middle], &points[middle + 1..]) } else { (&points[middle + 1..], &points[..middle]) };
+    nearest(near, depth + 1, query, k, best);
+    if best.len() < k || offset.abs() < best[best.len() - 1].0 {
+        nearest(far, depth + 1, query, k, best);
+    }
+}
+
+fn within(points: &[Point], depth: usize, query: &[f64; 3], radius: f64, found: &mut Vec<Point>) {
+    if points.is_empty() {
+        return
+    }
+    let axis = depth % 3;
+    let middle = points.len() / 2;
+    let root = points[middle];
+    // Small tolerance so points exactly on the radius are kept, haversine decides afterwards
+    if chord(&root.xyz, query) <= radius + 1e-12 {
+        found.push(root);
+    }
+    let offset = query[axis] - root.xyz[axis];
+    if offset - radius <= 1e-12 {
+        within(&points[..middle], depth + 1, query, radius, found);
+    }
+    if offset + radius >= -1e-12 {
+        within(&points[middle + 1..], depth + 1, query, radius, found);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(target_arch = "wasm32"))]
+    use proptest::prelude::*;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn brute_force(point: (f64, f64)) -> Vec<(LocalityId, f64)> {
+        let mut all: Vec<(LocalityId, f64)> = LocalityId::ALL.iter()
+            .map(|id| (*id, haversine_km(point, id.locality_lat_long().unwrap())))
+            .collect();
+        all.sort_by(|a, b| a.1.total_cmp(&b.1));
+        all
+    }
+
+    #[test]
+    fn test_nearest_station_is_itself() {
+        let index = SpatialIndex::builtin();
+        assert_eq!(index.len(), LocalityId::ALL.len());
+        for id in LocalityId::ALL.iter().step_by(17) {
+            let (nearest, distance) = index.nearest(id.locality_lat_long().unwrap(), 1)[0];
+            assert!(distance < 1e-6, "{} {}", nearest, distance);
+        }
+        assert!(index.nearest((12.9, 77.5), 0).is_empty());
+    }
+
+    #[test]
+    fn test_within_bbox() {
+        let bbox = BoundingBox::city("Surat").unwrap();
+        let found = SpatialIndex::builtin().within_bbox(bbox);
+        assert_eq!(found.len(), LocalityId::in_city("Surat").count());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    proptest! {
+        #[test]
+        fn prop_nearest_matches_brute_force(lat in 5.0..38.0f64, long in 67.0..98.0f64, k in 1..12usize) {
+            let expected = brute_force((lat, long));
+            let found = SpatialIndex::builtin().nearest((lat, long), k);
+            prop_assert_eq!(found.len(), k);
+            for (found, expected) in found.iter().zip(&expected) {
+                prop_assert!((found.1 - expected.1).abs() < 1e-6);
+            }
+        }
+
+        #[test]
+        fn prop_radius_matches_brute_force(lat in 8.0..33.0f64, long in 72.0..92.0f64, km in 0.0..60.0f64) {
+            let expected: Vec<(LocalityId, f64)> = brute_force((lat, long)).into_iter().filter(|(_, d)| *d <= km).collect();
+            let found = SpatialIndex::builtin().within_radius((lat, long), km);
+            prop_assert_eq!(found.len(), expected.len());
+            for (found, expected) in found.iter().zip(&expected) {
+                prop_assert!((found.1 - expected.1).abs() < 1e-6);
+            }
+        }
+
+        #[test]
+        fn prop_bbox_matches_brute_force(south in 8.0..33.0f64, west in 72.0..92.0f64, size in 0.0..2.0f64) {
+            let bbox = BoundingBox { south, west, north: south + size, east: west + size };
+            let mut expected: Vec<LocalityId> = LocalityId::ALL.iter().copied()
+                .filter(|id| bbox.contains(id.locality_lat_long().unwrap())).collect();
+            let mut found = SpatialIndex::builtin().within_bbox(bbox);
+            expected.sort_by_key(|id| id.0);
+            found.sort_by_key(|id| id.0);
+            prop_assert_eq!(found, expected);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+// Parsing and validation of the `localities.txt` format.
+// Shared with build.rs through `#[path]`, so this file may only depend on std.
+
+/// Approximate bounds of India, every WeatherUnion station lies inside them
+const INDIA_LAT: (f64, f64) = (6.0, 37.5);
+const INDIA_LONG: (f64, f64) = (68.0, 97.5);
+
+/// One line of the table
+pub struct TableLine<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub lat: f64,
+    pub long: f64,
+    pub device_type: u8,
+}
+
+/// Parse `<name> <id> <lat> <long> <device type> - <description>`
+pub fn parse_line(line: &str) -> Result<TableLine<'_>, String> {
+    let format = || "expected '<name> <id> <lat> <long> <device type> - <description>'".to_string();
+    // Names may contain ' - ' and anything else, descriptions do not, so split from the right
+    let (columns, _description) = line.rsplit_once(" - ").ok_or_else(format)?;
+    let mut rest = columns.trim_end();
+    let mut fields = [""; 4];
+    for field in fields.iter_mut().rev() {
+        let (before, last) = rest.rsplit_once(char::is_whitespace).ok_or_else(format)?;
+        *field = last;
+        rest = before.trim_end();
+    }
+    let [id, lat, long, device_type] = fields;
+    let name = rest.trim();
+    if name.is_empty() {
+        return Err(format())
+    }
+    let lat = lat.parse().map_err(|_| format!("invalid latitude for {id}"))?;
+    let long = long.parse().map_err(|_| format!("invalid longitude for {id}"))?;
+    let device_type = device_type.parse().map_err(|_| format!("invalid device type for {id}"))?;
+    let parsed = TableLine { id, name, lat, long, device_type };
+    validate(parsed.id, parsed.lat, parsed.long, parsed.device_type)?;
+    Ok(parsed)
+}
+
+/// Check an id is `ZWL` followed by six digits, the coordinates lie in India
+/// and the device is an automated weather system (1) or a rain gauge system (2).
+pub fn validate(id: &str, lat: f64, long: f64, device_type: u8) -> Result<(), String> {
+    let digits = id.strip_prefix("ZWL").unwrap_or("");
+    if digits.len() != 6 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("invalid locality id '{id}'"))
+    }
+    if !(INDIA_LAT.0..=INDIA_LAT.1).contains(&lat) || !(INDIA_LONG.0..=INDIA_LONG.1).contains(&long) {
+        return Err(format!("coordinates ({lat}, {long}) of {id} are outside India"))
+    }
+    if device_type != 1 && device_type != 2 {
+        return Err(format!("unknown device type {device_type} for {id}"))
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let parsed = parse_line("Pune Hinjewadi - Phase 2 ZWL004962 18.585004 73.706319 1 - Automated weather system").unwrap();
+        assert_eq!((parsed.name, parsed.id, parsed.lat, parsed.long, parsed.device_type),
+                   ("Pune Hinjewadi - Phase 2", "ZWL004962", 18.585004, 73.706319, 1));
+
+        // Tokens shaped like ids in the name, and the id itself repeated, stay in the name
+        let parsed = parse_line("Old ZWL000001 ZWL004962 1 2 1 - Tower ZWL004962 18.5 73.7 2 - Rain gauge system").unwrap();
+        assert_eq!((parsed.name, parsed.id, parsed.device_type), ("Old ZWL000001 ZWL004962 1 2 1 - Tower", "ZWL004962", 2));
+        let parsed = parse_line("Tower ZWL004962 18.5 73.7 2 - Replaces ZWL004962").unwrap();
+        assert_eq!((parsed.name, parsed.id), ("Tower", "ZWL004962"));
+
+        assert!(parse_line("ZWL004962 18.5 73.7 2 - Rain gauge system").is_err());
+        assert!(parse_line("Pune ZWL004962 18.5 73.7 2 Rain gauge system").is_err());
+        assert!(parse_line("Pune ZWL004962 18.5 2 - Rain gauge system").is_err());
+        assert_eq!(parse_line("Pune ZWL004962 north 73.7 2 - Rain gauge system").err().unwrap(), "invalid latitude for ZWL004962");
+    }
+}
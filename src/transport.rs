@@ -0,0 +1,372 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use crate::cassette::CassetteError;
+
+/// Header carrying the api key, redacted by the `Debug` of [`HttpRequest`]
+pub const API_KEY_HEADER: &str = "x-zomato-api-key";
+
+/// An HTTP request as handed to a [`Transport`]
+#[derive(Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl HttpRequest {
+
+    pub fn get(url: impl Into<String>) -> HttpRequest {
+        HttpRequest { method: "GET".to_string(), url: url.into(), headers: Vec::new() }
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> HttpRequest {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Value of the first header called `name`, ignoring case
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// The url without scheme and host, e.g. `/gw/weather/external/v0/get_weather_data?latitude=..`
+    pub fn path(&self) -> &str {
+        match self.url.split_once("://") {
+            Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]),
+            None => &self.url,
+        }
+    }
+}
+
+/// Shows every header but the api key, like the `Debug` of [`crate::keys::ApiKey`]
+impl fmt::Debug for HttpRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let headers: Vec<(&str, &str)> = self.headers.iter()
+            .map(|(name, value)| (name.as_str(), if name.eq_ignore_ascii_case(API_KEY_HEADER) { "<redacted>" } else { value.as_str() }))
+            .collect();
+        f.debug_struct("HttpRequest")
+            .field("method", &self.method)
+            .field("url", &self.url)
+            .field("headers", &headers)
+            .finish()
+    }
+}
+
+/// An HTTP response as returned by a [`Transport`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpResponse {
+
+    pub fn new(status: u16, body: impl Into<String>) -> HttpResponse {
+        HttpResponse { status, headers: Vec::new(), body: body.into() }
+    }
+}
+
+#[derive(Debug)]
+pub enum TransportError {
+    /// The request could not be sent or the response could not be read
+    Request(String),
+    Cassette(CassetteError),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<HttpResponse, TransportError>> + Send + 'a>>;
+/// Browser futures are tied to the JS event loop and never `Send`
+#[cfg(target_arch = "wasm32")]
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<HttpResponse, TransportError>> + 'a>>;
+
+/// Sends requests for a [`crate::WeatherUnion`], `ReqwestTransport` (or `FetchTransport` on wasm32) unless replaced with
+/// [`crate::WeatherUnion::with_transport`].\
+/// Any status code is a successful response, errors are for requests that got no response at all.
+/// # Example usage
+/// ```
+/// use weather_union::transport::{HttpRequest, HttpResponse, Transport, TransportFuture};
+/// struct Offline;
+/// impl Transport for Offline {
+///     fn send(&self, _request: HttpRequest) -> TransportFuture<'_> {
+///         Box::pin(async { Ok(HttpResponse::new(500, "")) })
+///     }
+/// }
+/// ```
+pub trait Transport: Send + Sync {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_>;
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        (**self).send(request)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        (**self).send(request)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn default_transport() -> Box<dyn Transport> {
+    Box::new(ReqwestTransport::new())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn default_transport() -> Box<dyn Transport> {
+    Box::new(FetchTransport::new())
+}
+
+/// The default transport, sharing one reqwest client (and its connection pool) across requests
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ReqwestTransport {
+
+    pub fn new() -> ReqwestTransport {
+        ReqwestTransport::default()
+    }
+
+    /// Use a preconfigured client, e.g. with timeouts or a proxy
+    pub fn with_client(client: reqwest::Client) -> ReqwestTransport {
+        ReqwestTransport { client }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Transport for ReqwestTransport {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let method = reqwest::Method::from_bytes(request.method.as_bytes())
+                .map_err(|e| TransportError::Request(e.to_string()))?;
+            let mut builder = self.client.request(method, &request.url);
+            for (name, value) in &request.headers {
+                builder = builder.header(name, value);
+            }
+            let response = builder.send().await.map_err(|e| TransportError::Request(e.to_string()))?;
+            let status = response.status().as_u16();
+            let headers = response.headers().iter()
+                .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+                .collect();
+            let body = response.text().await.map_err(|e| TransportError::Request(e.to_string()))?;
+            Ok(HttpResponse { status, headers, body })
+        })
+    }
+}
+
+/// The default transport on wasm32, sending requests with the global `fetch` of the browser, worker or node.\
+/// Browsers only allow the `x-zomato-api-key` header if the server answers the CORS preflight,
+/// dashboards usually point [`crate::WeatherUnion::with_base_url`] at their own proxy.
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FetchTransport;
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = fetch)]
+    fn fetch_with_request(request: &web_sys::Request) -> js_sys::Promise;
+}
+
+#[cfg(target_arch = "wasm32")]
+impl FetchTransport {
+
+    pub fn new() -> FetchTransport {
+        FetchTransport
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Transport for FetchTransport {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        use wasm_bindgen::JsCast;
+        use wasm_bindgen_futures::JsFuture;
+
+        let js_error = |e: wasm_bindgen::JsValue| TransportError::Request(format!("{:?}", e));
+        Box::pin(async move {
+            let headers = web_sys::Headers::new().map_err(js_error)?;
+            for (name, value) in &request.headers {
+                headers.set(name, value).map_err(js_error)?;
+            }
+            let init = web_sys::RequestInit::new();
+            init.set_method(&request.method);
+            init.set_headers(&headers);
+            let fetch_request = web_sys::Request::new_with_str_and_init(&request.url, &init).map_err(js_error)?;
+            let response: web_sys::Response = JsFuture::from(fetch_with_request(&fetch_request)).await
+                .map_err(js_error)?
+                .dyn_into()
+                .map_err(js_error)?;
+            let mut headers = Vec::new();
+            if let Ok(Some(entries)) = js_sys::try_iter(&response.headers()) {
+                for entry in entries.flatten() {
+                    let pair = js_sys::Array::from(&entry);
+                    if let (Some(name), Some(value)) = (pair.get(0).as_string(), pair.get(1).as_string()) {
+                        headers.push((name, value));
+                    }
+                }
+            }
+            let body = JsFuture::from(response.text().map_err(js_error)?).await.map_err(js_error)?;
+            Ok(HttpResponse { status: response.status(), headers, body: body.as_string().unwrap_or_default() })
+        })
+    }
+}
+
+/// In-memory transport answering requests with queued responses, in order.\
+/// Share it through an `Arc` to inspect the requests afterwards.
+/// # Example usage
+/// ```
+/// use std::sync::Arc;
+/// use weather_union::WeatherUnion;
+/// use weather_union::transport::{FakeTransport, HttpResponse};
+/// let fake = Arc::new(FakeTransport::new());
+/// fake.push(HttpResponse::new(429, ""));
+/// let client = WeatherUnion::from_key("api_key".to_string()).with_transport(fake.clone());
+/// let result = tokio_test::block_on(client.locality_id("ZWL005764"));
+/// assert!(result.is_err());
+/// assert_eq!(fake.requests()[0].header_value("x-zomato-api-key"), Some("api_key"));
+/// ```
+#[derive(Debug, Default)]
+pub struct FakeTransport {
+    responses: Mutex<VecDeque<Result<HttpResponse, String>>>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl FakeTransport {
+
+    pub fn new() -> FakeTransport {
+        FakeTransport::default()
+    }
+
+    /// Queue a response for the next request
+    pub fn push(&self, response: HttpResponse) {
+        self.responses.lock().unwrap().push_back(Ok(response));
+    }
+
+    /// Fail the next request as if the connection was lost
+    pub fn push_error(&self, message: impl Into<String>) {
+        self.responses.lock().unwrap().push_back(Err(message.into()));
+    }
+
+    /// Requests received so far, oldest first
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Transport for FakeTransport {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        let next = self.responses.lock().unwrap().pop_front();
+        let result = match next {
+            Some(Ok(response)) => Ok(response),
+            Some(Err(message)) => Err(TransportError::Request(message)),
+            None => Err(TransportError::Request(format!("no response queued for {} {}", request.method, request.url))),
+        };
+        self.requests.lock().unwrap().push(request);
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{WeatherResponseError, WeatherUnion};
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    fn client(responses: &[HttpResponse]) -> (WeatherUnion, Arc<FakeTransport>) {
+        let fake = Arc::new(FakeTransport::new());
+        for response in responses {
+            fake.push(response.clone());
+        }
+        (WeatherUnion::from_key("key".to_string()).with_transport(fake.clone()), fake)
+    }
+
+    #[test]
+    fn test_request_shape() {
+        let (client, fake) = client(&[HttpResponse::new(200, r#"{"message":"","device_type":1,"locality_weather_data":{}}"#)]);
+        aw!(client.lat_long(12.936787, 77.556079)).unwrap();
+        let request = &fake.requests()[0];
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path(), "/gw/weather/external/v0/get_weather_data?latitude=12.936787&longitude=77.556079");
+        assert_eq!(request.header_value("X-Zomato-Api-Key"), Some("key"));
+        let debug = format!("{:?}", request);
+        assert!(debug.contains("(\"x-zomato-api-key\", \"<redacted>\")") && !debug.contains("\"key\""));
+    }
+
+    #[test]
+    fn test_invalid_key_is_not_sent() {
+        let fake = Arc::new(FakeTransport::new());
+        let client = WeatherUnion::from_key("key\n".to_string()).with_transport(fake.clone());
+        fake.push(HttpResponse::new(200, r#"{"message":"","device_type":1,"locality_weather_data":{}}"#));
+        aw!(client.locality_id("ZWL005764")).unwrap();
+        // Trailing newline trimmed
+        assert_eq!(fake.requests()[0].header_value("x-zomato-api-key"), Some("key"));
+
+        let client = WeatherUnion::from_key(String::new()).with_transport(fake.clone());
+        assert!(matches!(aw!(client.locality_id("ZWL005764")), Err(WeatherResponseError::InvalidApiKey(_))));
+        assert_eq!(fake.requests().len(), 1);
+
+        let client = WeatherUnion::from_pool(crate::keys::KeyPool::new([] as [&str; 0])).with_transport(fake.clone());
+        assert!(matches!(aw!(client.locality_id("ZWL005764")), Err(WeatherResponseError::NoApiKeys)));
+        assert_eq!(fake.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_payload_values() {
+        let body = r#"{"status":"200","message":"","device_type":2,"locality_weather_data":
+            {"temperature":null,"rain_intensity":0.5,"rain_accumulation":3.25}}"#;
+        let (client, _) = client(&[HttpResponse::new(200, body)]);
+        let data = aw!(client.locality_id("ZWL008436")).unwrap();
+        assert_eq!(data.device, 2);
+        // Missing and null values read as zero
+        assert_eq!((data.temperature, data.humidity), (0.0, 0.0));
+        assert_eq!((data.rain_intensity, data.rain_accumulation), (0.5, 3.25));
+
+        let body = r#"{"status":"200","message":"","device_type":1,"locality_weather_data":
+            {"temperature":24.5,"humidity":null,"wind_speed":1.5,"wind_direction":90,"rain_intensity":0}}"#;
+        let (aws_client, _) = self::client(&[HttpResponse::new(200, body)]);
+        let reading = aw!(aws_client.locality_id_reading("ZWL005764")).unwrap();
+        // also for metrics the device measures, which are then missing
+        assert_eq!((reading.data.humidity, reading.data.rain_accumulation), (0.0, 0.0));
+        assert_eq!(reading.missing.iter().collect::<Vec<_>>(), [crate::Metric::Humidity, crate::Metric::RainAccumulation]);
+        assert_eq!(reading.metric(crate::Metric::Humidity), None);
+        assert_eq!(reading.metric(crate::Metric::WindSpeed), Some(1.5));
+    }
+
+    #[test]
+    fn test_payload_errors() {
+        let (client, _) = client(&[
+            HttpResponse::new(200, r#"{"message":"data not available","device_type":1,"locality_weather_data":{}}"#),
+            HttpResponse::new(200, "<html>"),
+            HttpResponse::new(500, ""),
+            HttpResponse::new(400, ""),
+            HttpResponse::new(429, ""),
+            HttpResponse::new(403, ""),
+            HttpResponse::new(418, ""),
+        ]);
+        let mut results = (0..8).map(|_| aw!(client.locality_id("ZWL005764")));
+        assert!(matches!(results.next(), Some(Err(WeatherResponseError::TemporarilyUnavailable(m))) if m == "data not available"));
+        assert!(matches!(results.next(), Some(Err(WeatherResponseError::InvalidResponse))));
+        assert!(matches!(results.next(), Some(Err(WeatherResponseError::ErrorRetrievingData))));
+        assert!(matches!(results.next(), Some(Err(WeatherResponseError::NotSupported))));
+        assert!(matches!(results.next(), Some(Err(WeatherResponseError::ApiKeyLimitExhausted))));
+        assert!(matches!(results.next(), Some(Err(WeatherResponseError::CouldNotAuthenticate))));
+        assert!(matches!(results.next(), Some(Err(WeatherResponseError::UnknownError(s))) if s.as_u16() == 418));
+        // Nothing left in the queue
+        assert!(matches!(results.next(), Some(Err(WeatherResponseError::Transport(TransportError::Request(_))))));
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use serde_json::{json, Value};
+
+use crate::geo::haversine_km;
+use crate::heatmap::BoundingBox;
+use crate::LocalityId;
+
+/// How far beyond its outermost stations a city's cells extend, in degrees (about 5 km)
+const CITY_MARGIN: f64 = 0.05;
+
+/// The area closer to one locality than to any other locality of its city
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cell {
+    pub id: LocalityId,
+    pub city: &'static str,
+    /// Convex polygon of (lat, long) vertices, not repeating the first vertex
+    pub polygon: Vec<(f64, f64)>,
+}
+
+impl Cell {
+
+    /// Returns true if (lat, long) lies inside or on the edge of the cell.
+    pub fn contains(&self, (lat, long): (f64, f64)) -> bool {
+        // Convex, so the point must be on the same side of every edge
+        let mut sign = 0.0;
+        for (i, a) in self.polygon.iter().enumerate() {
+            let b = self.polygon[(i + 1) % self.polygon.len()];
+            let cross = (b.1 - a.1) * (lat - a.0) - (b.0 - a.0) * (long - a.1);
+            if cross.abs() < 1e-12 {
+                continue
+            }
+            if sign == 0.0 {
+                sign = cross.signum();
+            } else if cross.signum() != sign {
+                return false
+            }
+        }
+        !self.polygon.is_empty()
+    }
+}
+
+/// Voronoi cells of the localities, computed per city and clipped to the city's extent
+/// # Example usage
+/// ```
+/// use weather_union::voronoi::VoronoiMap;
+/// let cells = VoronoiMap::builtin();
+/// if let Some(id) = cells.locate((12.936787, 77.556079)) {
+///     println!("covered by {}", id);
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoronoiMap {
+    cells: Vec<Cell>,
+    bounds: BTreeMap<&'static str, BoundingBox>,
+}
+
+impl VoronoiMap {
+
+    /// Compute cells for the given localities, those without a city or coordinates are skipped.
+    pub fn new(ids: impl IntoIterator<Item = LocalityId>) -> VoronoiMap {
+        VoronoiMap::with_margin(ids, CITY_MARGIN)
+    }
+
+    fn with_margin(ids: impl IntoIterator<Item = LocalityId>, margin: f64) -> VoronoiMap {
+        let mut cities: BTreeMap<&'static str, Vec<LocalityId>> = BTreeMap::new();
+        for id in ids {
+            if let Some(city) = id.city() {
+                cities.entry(city).or_default().push(id);
+            }
+        }
+        let mut cells = Vec::new();
+        let mut bounds = BTreeMap::new();
+        for (city, ids) in cities {
+            let stations: Vec<(LocalityId, (f64, f64))> = ids.iter().filter_map(|id| Some((*id, id.locality_lat_long()?))).collect();
+            let Some(bbox) = BoundingBox::around(ids) else { continue };
+            let bbox = bbox.pad(margin);
+            bounds.insert(city, bbox);
+            // Equirectangular projection around the city so distances are comparable in both axes
+            let origin = ((bbox.north + bbox.south) / 2.0, (bbox.east + bbox.west) / 2.0);
+            let scale = origin.0.to_radians().cos();
+            let project = |(lat, long): (f64, f64)| ((long - origin.1) * scale, lat - origin.0);
+            let unproject = |(x, y): (f64, f64)| (y + origin.0, x / scale + origin.1);
+
+            let extent = vec![
+                project((bbox.north, bbox.west)), project((bbox.north, bbox.east)),
+                project((bbox.south, bbox.east)), project((bbox.south, bbox.west)),
+            ];
+            for (id, lat_long) in &stations {
+                let site = project(*lat_long);
+                let mut polygon = extent.clone();
+                for (_, other) in &stations {
+                    let other = project(*other);
+                    if other != site {
+                        polygon = clip_closer(&polygon, site, other);
+                    }
+                }
+                cells.push(Cell { id: *id, city, polygon: polygon.into_iter().map(unproject).collect() });
+            }
+        }
+        VoronoiMap { cells, bounds }
+    }
+
+    /// Cells of every built-in locality, computed on first use
+    pub fn builtin() -> &'static VoronoiMap {
+        static BUILTIN: OnceLock<VoronoiMap> = OnceLock::new();
+        BUILTIN.get_or_init(|| VoronoiMap::new(LocalityId::ALL.iter().copied()))
+    }
+
+    /// The locality whose cell contains (lat, long), None outside every city.
+    /// Where the extents of cities overlap the point lies in a cell of each, the nearest station wins.
+    pub fn locate(&self, point: (f64, f64)) -> Option<LocalityId> {
+        let cities: Vec<&str> = self.bounds.iter().filter(|(_, b)| b.contains(point)).map(|(c, _)| *c).collect();
+        self.cells.iter()
+            .filter(|cell| cities.contains(&cell.city) && cell.contains(point))
+            .filter_map(|cell| Some((cell.id, haversine_km(point, cell.id.locality_lat_long()?))))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(id, _)| id)
+    }
+
+    pub fn cell(&self, id: LocalityId) -> Option<&Cell> {
+        self.cells.iter().find(|cell| cell.id == id)
+    }
+
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    /// GeoJSON FeatureCollection with a polygon per cell
+    pub fn to_geojson(&self) -> Value {
+        let features: Vec<Value> = self.cells.iter().map(|cell| {
+            let mut ring: Vec<[f64; 2]> = cell.polygon.iter().map(|(lat, long)| [*long, *lat]).collect();
+            if let Some(first) = ring.first().copied() {
+                ring.push(first);
+            }
+            json!({
+                "type": "Feature",
+                "id": cell.id.0,
+                "geometry": { "type": "Polygon", "coordinates": [ring] },
+                "properties": { "id": cell.id.0, "name": cell.id.locality_name(), "city": cell.city },
+            })
+        }).collect();
+        json!({ "type": "FeatureCollection", "features": features })
+    }
+}
+
+/// Sutherland-Hodgman clip of a convex polygon to the half plane closer to `site` than to `other`.
+fn clip_closer(polygon: &[(f64, f64)], site: (f64, f64), other: (f64, f64)) -> Vec<(f64, f64)> {
+    // Signed distance past the perpendicular bisector, negative on the site's side
+    let normal = (other.0 - site.0, other.1 - site.1);
+    let middle = ((site.0 + other.0) / 2.0, (site.1 + other.1) / 2.0);
+    let side = |p: (f64, f64)| (p.0 - middle.0) * normal.0 + (p.1 - middle.1) * normal.1;
+
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (i, current) in polygon.iter().enumerate() {
+        let next = polygon[(i + 1) % polygon.len()];
+        let (a, b) = (side(*current), side(next));
+        if a <= 0.0 {
+            clipped.push(*current);
+        }
+        if (a < 0.0 && b > 0.0) || (a > 0.0 && b < 0.0) {
+            let t = a / (a - b);
+            clipped.push((current.0 + t * (next.0 - current.0), current.1 + t * (next.1 - current.1)));
+        }
+    }
+    clipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_station_lies_in_own_cell() {
+        let map = VoronoiMap::builtin();
+        assert_eq!(map.cells().len(), LocalityId::ALL.len());
+        for cell in map.cells() {
+            let station = cell.id.locality_lat_long().unwrap();
+            assert!(cell.polygon.len() >= 3, "{}", cell.id);
+            assert!(cell.contains(station), "{} not in its own cell", cell.id);
+            let located = map.locate(station).unwrap();
+            // Stations sharing coordinates share a cell
+            assert_eq!(located.locality_lat_long(), Some(station));
+        }
+    }
+
+    #[test]
+    fn test_locate() {
+        let map = VoronoiMap::builtin();
+        // Next to Sarita Vihar, far from every other station
+        assert_eq!(map.locate((28.5320, 77.2942)), Some(LocalityId::ZWL005764));
+        assert_eq!(map.locate((0.0, 0.0)), None);
+        let single = map.cell(LocalityId::ZWL002458).unwrap(); // Mysore has one station
+        assert_eq!(single.polygon.len(), 4);
+    }
+
+    #[test]
+    fn test_overlapping_cities() {
+        // Wide enough margins for the extents of Mumbai and Pune to overlap
+        let ids: Vec<LocalityId> = LocalityId::in_city("Mumbai").chain(LocalityId::in_city("Pune")).collect();
+        let map = VoronoiMap::with_margin(ids.iter().copied(), 0.5);
+        let (mumbai, pune) = (map.bounds["Mumbai"], map.bounds["Pune"]);
+        let (south, north) = (mumbai.south.max(pune.south), mumbai.north.min(pune.north));
+        let (west, east) = (mumbai.west.max(pune.west), mumbai.east.min(pune.east));
+        assert!(south < north && west < east);
+        for i in 0..=10 {
+            for j in 0..=10 {
+                let point = (south + (north - south) * i as f64 / 10.0, west + (east - west) * j as f64 / 10.0);
+                let nearest = ids.iter().map(|id| id.locality_lat_long().unwrap())
+                    .min_by(|a, b| haversine_km(point, *a).total_cmp(&haversine_km(point, *b)));
+                assert_eq!(map.locate(point).and_then(|id| id.locality_lat_long()), nearest, "{:?}", point);
+            }
+        }
+        // Inside Mumbai's extent but nearer to Pune
+        assert_eq!(map.locate((18.5, 73.5)).and_then(|id| id.city()), Some("Pune"));
+    }
+
+    #[test]
+    fn test_geojson() {
+        let geojson = VoronoiMap::new(LocalityId::in_city("Surat")).to_geojson();
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 8);
+        let ring = features[0]["geometry"]["coordinates"][0].as_array().unwrap();
+        assert_eq!(ring.first(), ring.last());
+    }
+}
@@ -0,0 +1,89 @@
+use std::rc::Rc;
+
+use js_sys::Promise;
+use serde_json::{json, Value};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::future_to_promise;
+
+use crate::{geojson, LocalityId, Metric, Reading, WeatherResponseError, WeatherUnion};
+
+/// JavaScript bindings, exported by wasm-bindgen.
+/// # Example usage
+/// ```js
+/// import init, { Client, searchLocalities } from "./weather_union.js";
+/// await init();
+/// const matches = searchLocalities("banashankari"); // GeoJSON FeatureCollection
+/// const client = new Client("api_key", "https://proxy.example.com");
+/// const reading = await client.locality(matches.features[0].id);
+/// console.log(reading.temperature);
+/// ```
+#[wasm_bindgen]
+pub struct Client {
+    inner: Rc<WeatherUnion>,
+}
+
+#[wasm_bindgen]
+impl Client {
+
+    /// Client sending requests with `fetch`, to `base_url` if given
+    #[wasm_bindgen(constructor)]
+    pub fn new(api_key: String, base_url: Option<String>) -> Client {
+        let mut client = WeatherUnion::from_key(api_key);
+        if let Some(base_url) = base_url {
+            client = client.with_base_url(base_url);
+        }
+        Client { inner: Rc::new(client) }
+    }
+
+    /// Resolves to the reading of a locality id, rejects with an error naming the failure
+    pub fn locality(&self, id: String) -> Promise {
+        let client = self.inner.clone();
+        future_to_promise(async move {
+            let id = LocalityId::from_str(&id).map_err(|_| js_error(format!("unknown locality id {}", id)))?;
+            reading(client.locality_reading(id).await)
+        })
+    }
+
+    /// Resolves to the reading of the station nearest to (lat, long)
+    #[wasm_bindgen(js_name = latLong)]
+    pub fn lat_long(&self, lat: f64, long: f64) -> Promise {
+        let client = self.inner.clone();
+        future_to_promise(async move { reading(client.lat_long_reading(lat, long).await) })
+    }
+}
+
+/// GeoJSON FeatureCollection of the localities matching every word of `query`, see [`LocalityId::search`]
+#[wasm_bindgen(js_name = searchLocalities)]
+pub fn search_localities(query: &str) -> JsValue {
+    to_js(&geojson::localities(LocalityId::search(query)))
+}
+
+/// GeoJSON FeatureCollection of every built-in locality
+#[wasm_bindgen]
+pub fn catalogue() -> JsValue {
+    to_js(&geojson::catalogue())
+}
+
+/// Reading as a plain object with the device type and one member per metric, null if not reported or missing
+pub fn reading_to_json(reading: &Reading) -> Value {
+    let mut object = json!({ "device": reading.data.device });
+    for metric in Metric::ALL {
+        object[metric.name()] = json!(reading.metric(metric));
+    }
+    object
+}
+
+fn reading(result: Result<Reading, WeatherResponseError>) -> Result<JsValue, JsValue> {
+    match result {
+        Ok(reading) => Ok(to_js(&reading_to_json(&reading))),
+        Err(error) => Err(js_error(format!("{:?}", error))),
+    }
+}
+
+fn to_js(value: &Value) -> JsValue {
+    js_sys::JSON::parse(&value.to_string()).unwrap_or(JsValue::NULL)
+}
+
+fn js_error(message: String) -> JsValue {
+    js_sys::Error::new(&message).into()
+}
@@ -0,0 +1,280 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::Duration;
+
+use futures_util::stream::{self, Stream};
+use tokio::time::Instant;
+
+use crate::{LocalityId, Reading, WeatherResponseError, WeatherUnion};
+
+/// Shortest interval a watch polls at, shorter intervals are raised to it.
+pub const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Options for [`WeatherUnion::watch_with`]
+/// # Example usage
+/// ```
+/// use std::time::Duration;
+/// use weather_union::watch::WatchOptions;
+/// let options = WatchOptions::new(Duration::from_secs(300))
+///     .rate_limit(10, Duration::from_secs(60)) // never more than 10 requests a minute
+///     .only_changed(true); // skip readings identical to the previous one
+/// ```
+#[derive(Clone, Debug)]
+pub struct WatchOptions {
+    interval: Duration,
+    min_spacing: Duration,
+    jitter: bool,
+    only_changed: bool,
+}
+
+impl WatchOptions {
+
+    /// Poll every locality once per `interval`, jittered, emitting every reading.\
+    /// An interval below [`MIN_INTERVAL`] is raised to it, so a zero interval does not poll in a tight loop.
+    pub fn new(interval: Duration) -> WatchOptions {
+        WatchOptions { interval: interval.max(MIN_INTERVAL), min_spacing: Duration::ZERO, jitter: true, only_changed: false }
+    }
+
+    /// Allow at most `requests` requests in any window of length `per`.\
+    /// Polls are spaced evenly, if the limit cannot fit every locality into one interval
+    /// the polls are delayed rather than dropped.\
+    /// A watch always polls, so a limit of zero `requests` is raised to one request per `per`.
+    pub fn rate_limit(mut self, requests: u32, per: Duration) -> WatchOptions {
+        self.min_spacing = per / requests.max(1);
+        self
+    }
+
+    /// Randomly offset each poll within its slot of the interval (enabled by default).
+    pub fn jitter(mut self, jitter: bool) -> WatchOptions {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Only emit successful readings that differ from the previous reading of the same locality.\
+    /// Errors are always emitted.
+    pub fn only_changed(mut self, only_changed: bool) -> WatchOptions {
+        self.only_changed = only_changed;
+        self
+    }
+}
+
+/// Decides which locality is polled next and when.
+struct Schedule {
+    ids: Vec<LocalityId>,
+    queue: BinaryHeap<Reverse<(Instant, usize)>>,
+    interval: Duration,
+    min_spacing: Duration,
+    last_request: Option<Instant>,
+}
+
+impl Schedule {
+    fn new(ids: Vec<LocalityId>, options: &WatchOptions, start: Instant, seed: u64) -> Schedule {
+        let slot = if ids.is_empty() { Duration::ZERO } else { options.interval / ids.len() as u32 };
+        let mut rng = XorShift(seed | 1);
+        let queue = (0..ids.len()).map(|i| {
+            let offset = if options.jitter { slot.mul_f64(rng.next_f64()) } else { Duration::ZERO };
+            Reverse((start + slot * i as u32 + offset, i))
+        }).collect();
+        Schedule { ids, queue, interval: options.interval, min_spacing: options.min_spacing, last_request: None }
+    }
+
+    /// Pops the next poll, returning when it may be sent and for which locality,
+    /// and queues the following poll of that locality one interval later.
+    fn next(&mut self) -> Option<(Instant, LocalityId)> {
+        let Reverse((due, index)) = self.queue.pop()?;
+        let at = match self.last_request {
+            Some(last) => due.max(last + self.min_spacing),
+            None => due,
+        };
+        self.last_request = Some(at);
+        self.queue.push(Reverse((due + self.interval, index)));
+        Some((at, self.ids[index]))
+    }
+}
+
+/// Minimal xorshift generator, good enough for spreading polls and mock readings.
+pub(crate) struct XorShift(pub(crate) u64);
+
+impl XorShift {
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+pub(crate) fn seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0x2545_f491_4f6c_dd1d)
+}
+
+impl WeatherUnion {
+
+    /// Poll a set of localities every `interval`, yielding each reading as it arrives.\
+    /// Polls are spread and jittered across the interval, see [`WeatherUnion::watch_with`] for more control.
+    /// # Example
+    /// ```
+    ///  use std::time::Duration;
+    ///  use futures_util::StreamExt;
+    ///  use weather_union::{LocalityId, WeatherUnion};
+    /// async fn example(){
+    ///     let client = WeatherUnion::from_key("api_key".to_string());
+    ///     let ids = [LocalityId::ZWL005764, LocalityId::ZWL008752];
+    ///     let mut readings = Box::pin(client.watch(ids, Duration::from_secs(600)));
+    ///     while let Some((id, data)) = readings.next().await {
+    ///         println!("{} {:?}", id, data);
+    ///     }
+    /// }
+    /// ```
+    pub fn watch(&self, ids: impl IntoIterator<Item = LocalityId>, interval: Duration)
+        -> impl Stream<Item = (LocalityId, Result<Reading, WeatherResponseError>)> + '_ {
+        self.watch_with(ids, WatchOptions::new(interval))
+    }
+
+    /// Poll a set of localities according to [`WatchOptions`].\
+    /// The stream never ends unless `ids` is empty.
+    pub fn watch_with(&self, ids: impl IntoIterator<Item = LocalityId>, options: WatchOptions)
+        -> impl Stream<Item = (LocalityId, Result<Reading, WeatherResponseError>)> + '_ {
+        let schedule = Schedule::new(ids.into_iter().collect(), &options, Instant::now(), seed());
+        let previous: HashMap<LocalityId, Reading> = HashMap::new();
+        stream::unfold((schedule, previous), move |(mut schedule, mut previous)| async move {
+            loop {
+                let (at, id) = schedule.next()?;
+                tokio::time::sleep_until(at).await;
+                let result = self.locality_reading(id).await;
+                if let Ok(data) = &result {
+                    if previous.insert(id, *data) == Some(*data) && options.only_changed {
+                        continue;
+                    }
+                }
+                return Some(((id, result), (schedule, previous)));
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures_util::StreamExt;
+
+    use super::*;
+    use crate::transport::{FakeTransport, HttpResponse};
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    fn body(device: u8, temperature: f64, rain_accumulation: f64) -> String {
+        format!(r#"{{"status":"200","message":"","device_type":{},"locality_weather_data":{{"temperature":{},"humidity":60.0,"wind_speed":1.0,"wind_direction":90.0,"rain_intensity":0.0,"rain_accumulation":{}}}}}"#,
+                device, temperature, rain_accumulation)
+    }
+
+    #[test]
+    fn test_watch_stream() {
+        aw!(async {
+            tokio::time::pause();
+            let fake = Arc::new(FakeTransport::new());
+            fake.push(HttpResponse::new(200, body(1, 24.0, 0.0)));
+            fake.push(HttpResponse::new(200, body(2, 0.0, 1.0)));
+            // Same as the previous reading of the first locality
+            fake.push(HttpResponse::new(200, body(1, 24.0, 0.0)));
+            fake.push(HttpResponse::new(200, body(2, 0.0, 1.5)));
+            fake.push(HttpResponse::new(500, ""));
+            fake.push(HttpResponse::new(200, body(2, 0.0, 2.0)));
+            let client = WeatherUnion::from_key("api_key".to_string()).with_transport(fake.clone());
+            let ids = [LocalityId::ZWL005764, LocalityId::ZWL008436];
+            let options = WatchOptions::new(Duration::from_secs(60)).jitter(false).only_changed(true);
+            let start = Instant::now();
+            let readings: Vec<(u64, LocalityId, Result<Reading, WeatherResponseError>)> = client.watch_with(ids, options)
+                .take(5)
+                .map(|(id, result)| ((Instant::now() - start).as_secs(), id, result))
+                .collect()
+                .await;
+
+            // Polls alternate half an interval apart, the unchanged reading at 60s is dropped
+            let seen: Vec<(u64, LocalityId)> = readings.iter().map(|(at, id, _)| (*at, *id)).collect();
+            assert_eq!(seen, [
+                (0, LocalityId::ZWL005764), (30, LocalityId::ZWL008436), (90, LocalityId::ZWL008436),
+                (120, LocalityId::ZWL005764), (150, LocalityId::ZWL008436),
+            ]);
+            assert_eq!(readings[2].2.as_ref().unwrap().data.rain_accumulation, 1.5);
+            // Errors are always emitted
+            assert!(matches!(readings[3].2, Err(WeatherResponseError::ErrorRetrievingData)));
+            assert_eq!(readings[4].2.as_ref().unwrap().data.rain_accumulation, 2.0);
+            let polled: Vec<bool> = fake.requests().iter().map(|r| r.url.contains("ZWL005764")).collect();
+            assert_eq!(polled, [true, false, true, false, true, false]);
+        });
+    }
+
+    #[test]
+    fn test_watch_emits_repeats_by_default() {
+        aw!(async {
+            tokio::time::pause();
+            let fake = Arc::new(FakeTransport::new());
+            for _ in 0..3 {
+                fake.push(HttpResponse::new(200, body(1, 24.0, 0.0)));
+            }
+            let client = WeatherUnion::from_key("api_key".to_string()).with_transport(fake.clone());
+            let start = Instant::now();
+            let options = WatchOptions::new(Duration::from_secs(10)).jitter(false).rate_limit(1, Duration::from_secs(25));
+            let times: Vec<u64> = client.watch_with([LocalityId::ZWL005764], options)
+                .take(3)
+                .map(|_| (Instant::now() - start).as_secs())
+                .collect()
+                .await;
+            // The rate limit spaces polls further apart than the interval
+            assert_eq!(times, [0, 25, 50]);
+        });
+    }
+
+    #[test]
+    fn test_schedule_spreads_polls() {
+        let start = Instant::now();
+        let ids = vec![LocalityId::ZWL005764, LocalityId::ZWL008752, LocalityId::ZWL005996];
+        let options = WatchOptions::new(Duration::from_secs(90));
+        let mut schedule = Schedule::new(ids, &options, start, 42);
+        for i in 0..3u32 {
+            let (at, _) = schedule.next().unwrap();
+            let offset = at - start;
+            assert!(offset >= Duration::from_secs(30) * i && offset < Duration::from_secs(30) * (i + 1));
+        }
+        // Second round starts one interval after the first
+        let (at, id) = schedule.next().unwrap();
+        assert_eq!(id, LocalityId::ZWL005764);
+        assert!(at - start >= Duration::from_secs(90));
+    }
+
+    #[test]
+    fn test_zero_options_are_raised() {
+        let start = Instant::now();
+        let ids = vec![LocalityId::ZWL005764, LocalityId::ZWL008752];
+        let options = WatchOptions::new(Duration::ZERO).jitter(false);
+        let mut schedule = Schedule::new(ids.clone(), &options, start, 42);
+        let times: Vec<Duration> = (0..4).map(|_| schedule.next().unwrap().0 - start).collect();
+        assert_eq!(times, [Duration::ZERO, MIN_INTERVAL / 2, MIN_INTERVAL, MIN_INTERVAL * 3 / 2]);
+
+        let options = WatchOptions::new(Duration::from_secs(1)).jitter(false).rate_limit(0, Duration::from_secs(10));
+        let mut schedule = Schedule::new(ids, &options, start, 42);
+        let times: Vec<Duration> = (0..3).map(|_| schedule.next().unwrap().0 - start).collect();
+        assert_eq!(times, [Duration::ZERO, Duration::from_secs(10), Duration::from_secs(20)]);
+    }
+
+    #[test]
+    fn test_schedule_rate_limit() {
+        let start = Instant::now();
+        let ids = vec![LocalityId::ZWL005764, LocalityId::ZWL008752, LocalityId::ZWL005996];
+        let options = WatchOptions::new(Duration::from_secs(3)).jitter(false).rate_limit(1, Duration::from_secs(10));
+        let mut schedule = Schedule::new(ids, &options, start, 42);
+        let times: Vec<Instant> = (0..6).map(|_| schedule.next().unwrap().0).collect();
+        for pair in times.windows(2) {
+            assert!(pair[1] - pair[0] >= Duration::from_secs(10));
+        }
+    }
+}
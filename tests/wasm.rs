@@ -0,0 +1,47 @@
+//! Headless checks of the wasm32 build, run with
+//! `cargo test --target wasm32-unknown-unknown --test wasm`
+//! with `wasm-bindgen-test-runner` as the target runner.
+#![cfg(target_arch = "wasm32")]
+
+use std::sync::Arc;
+
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::wasm_bindgen_test;
+use weather_union::transport::{FakeTransport, HttpResponse};
+use weather_union::wasm::{search_localities, Client};
+use weather_union::{LocalityId, Metric, WeatherUnion};
+
+fn get(value: &JsValue, key: &str) -> JsValue {
+    js_sys::Reflect::get(value, &JsValue::from_str(key)).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn catalogue_works_offline() {
+    assert_eq!(LocalityId::ZWL005764.locality_name(), Some("Delhi NCR Sarita Vihar"));
+    assert!(LocalityId::in_city("Surat").count() > 0);
+    assert_eq!(Metric::RainIntensity.name(), "rain_intensity");
+
+    let found = search_localities("sarita vihar");
+    let features = js_sys::Array::from(&get(&found, "features"));
+    assert_eq!(features.length(), 1);
+    assert_eq!(get(&features.get(0), "id").as_string().as_deref(), Some("ZWL005764"));
+}
+
+#[wasm_bindgen_test]
+async fn client_over_fake_transport() {
+    let fake = Arc::new(FakeTransport::new());
+    fake.push(HttpResponse::new(200, r#"{"message":"","device_type":2,"locality_weather_data":{"rain_intensity":1.5}}"#));
+    let client = WeatherUnion::from_key("key".to_string()).with_transport(fake.clone());
+    let data = client.locality(LocalityId::ZWL008436).await.unwrap();
+    assert_eq!((data.device, data.rain_intensity), (2, 1.5));
+    assert_eq!(fake.requests()[0].header_value("x-zomato-api-key"), Some("key"));
+}
+
+#[wasm_bindgen_test]
+async fn bindings_reject_unknown_ids() {
+    let client = Client::new("key".to_string(), Some("http://127.0.0.1:9".to_string()));
+    let result = JsFuture::from(client.locality("ZWL000000".to_string())).await;
+    let message = get(&result.unwrap_err(), "message").as_string().unwrap();
+    assert!(message.contains("ZWL000000"), "{}", message);
+}